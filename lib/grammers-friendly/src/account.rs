@@ -0,0 +1,55 @@
+// Copyright (C) 2024 AndrielFR
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::sync::Arc;
+
+use grammers_client::Client;
+
+use crate::traits::Module;
+
+/// Name of the account used when none is given.
+pub const DEFAULT_ACCOUNT: &str = "default";
+
+/// An Account.
+///
+/// Tags each update with the client which received it.
+///
+/// Available to the handlers and middlewares as a module of the [`Data`],
+/// And to the filters through the [`Context`].
+///
+/// [`Data`]: crate::Data
+/// [`Context`]: crate::Context
+#[derive(Clone)]
+pub struct Account {
+    name: Arc<str>,
+    client: Client,
+}
+
+impl Account {
+    /// Construct a new account.
+    ///
+    /// Receives its `name`, which should be unique, and its `client`.
+    pub fn new(name: impl Into<String>, client: Client) -> Self {
+        Self {
+            name: Arc::from(name.into()),
+            client,
+        }
+    }
+
+    /// Get the account name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the account client.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl Module for Account {}
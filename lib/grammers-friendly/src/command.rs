@@ -0,0 +1,715 @@
+// Copyright (C) 2024 AndrielFR
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::{error::Error as StdError, fmt, time::Duration};
+
+use grammers_client::{grammers_tl_types as tl, Client, Update};
+
+use crate::{
+    traits::{FromCommandArg, FromCommandArgs, FromUpdate},
+    Context, Data, Result,
+};
+
+/// Command arguments.
+///
+/// The command parsed by the last matched [`CommandFilter`], left in the [`Context`].
+///
+/// Taken by the handlers as an extractor.
+///
+/// [`CommandFilter`]: crate::filters::CommandFilter
+/// [`Context`]: crate::Context
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommandArgs {
+    prefix: Option<char>,
+    command: String,
+    mention: Option<String>,
+    raw: String,
+    args: Vec<String>,
+    offsets: Vec<usize>,
+}
+
+impl CommandArgs {
+    /// Get the prefix used, if the command has prefixes.
+    pub fn prefix(&self) -> Option<char> {
+        self.prefix
+    }
+
+    /// Get the command name, as given.
+    ///
+    /// May be one of its aliases.
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// Get the mentioned username, as in `/start@username`.
+    pub fn mention(&self) -> Option<&str> {
+        self.mention.as_deref()
+    }
+
+    /// Get the text after the command, untouched.
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    /// Get the arguments, split like a shell does.
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Get the argument at `index`.
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.args.get(index).map(String::as_str)
+    }
+
+    /// Get the number of arguments.
+    pub fn len(&self) -> usize {
+        self.args.len()
+    }
+
+    /// Checks if there are no arguments.
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty()
+    }
+}
+
+/// Pending arguments.
+///
+/// The command arguments not taken yet, see [`FromCommandArg`].
+///
+/// [`FromCommandArg`]: crate::traits::FromCommandArg
+#[derive(Clone, Copy, Debug)]
+pub struct PendingArgs<'a> {
+    args: &'a CommandArgs,
+    pos: usize,
+}
+
+impl<'a> PendingArgs<'a> {
+    /// Construct new pending arguments, with all the `args`.
+    pub fn new(args: &'a CommandArgs) -> Self {
+        Self { args, pos: 0 }
+    }
+
+    /// Get the next argument, without taking it.
+    pub fn peek(&self) -> Option<&'a str> {
+        self.args.get(self.pos)
+    }
+
+    /// Take the next argument.
+    pub fn pop_front(&mut self) -> Option<String> {
+        let arg = self.peek()?;
+        self.pos += 1;
+
+        Some(arg.to_string())
+    }
+
+    /// Take all the remaining arguments, as the untouched text from the next one.
+    pub fn take_raw(&mut self) -> &'a str {
+        let raw = match self.args.offsets.get(self.pos) {
+            Some(&offset) => &self.args.raw[offset..],
+            None => "",
+        };
+        self.pos = self.args.len();
+
+        raw
+    }
+
+    /// Checks if all the arguments were taken.
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.args.len()
+    }
+}
+
+impl FromUpdate for CommandArgs {
+    fn from_update(_: &Client, _: &Update, _: &Data, context: &Context) -> Result<Option<Self>> {
+        Ok(context.get::<Self>().cloned())
+    }
+}
+
+/// A user given as argument: `@username` or its id.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UserRef {
+    /// The username, without `@`.
+    Username(String),
+
+    /// The id.
+    Id(i64),
+}
+
+impl FromCommandArg for UserRef {
+    fn from_command_arg(args: &mut PendingArgs) -> std::result::Result<Self, ArgError> {
+        let arg = args.pop_front().ok_or(ArgError::Missing)?;
+
+        if let Some(username) = arg
+            .strip_prefix('@')
+            .filter(|username| !username.is_empty())
+        {
+            return Ok(Self::Username(username.to_string()));
+        }
+
+        arg.parse()
+            .map(Self::Id)
+            .map_err(|_| ArgError::Invalid(arg))
+    }
+}
+
+/// The rest of the arguments, as the untouched text, with its quotes and newlines.
+///
+/// Must be the last field.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rest(pub String);
+
+impl std::ops::Deref for Rest {
+    type Target = String;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl FromCommandArg for Rest {
+    fn from_command_arg(args: &mut PendingArgs) -> std::result::Result<Self, ArgError> {
+        match args.take_raw() {
+            "" => Err(ArgError::Missing),
+            raw => Ok(Self(raw.to_string())),
+        }
+    }
+
+    fn usage(name: &str) -> String {
+        format!("<{}...>", name)
+    }
+}
+
+/// A duration as `1w2d3h4m5s`, any of the units may be omitted.
+impl FromCommandArg for Duration {
+    fn from_command_arg(args: &mut PendingArgs) -> std::result::Result<Self, ArgError> {
+        let arg = args.pop_front().ok_or(ArgError::Missing)?;
+
+        parse_duration(&arg).ok_or(ArgError::Invalid(arg))
+    }
+}
+
+/// Parse a duration as `1w2d3h4m5s`.
+fn parse_duration(text: &str) -> Option<Duration> {
+    let mut secs = 0u64;
+    let mut number = String::new();
+
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let unit = match c.to_ascii_lowercase() {
+            'w' => 7 * 24 * 60 * 60,
+            'd' => 24 * 60 * 60,
+            'h' => 60 * 60,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        let value: u64 = number.parse().ok()?;
+        secs = secs.checked_add(value.checked_mul(unit)?)?;
+
+        number.clear();
+    }
+
+    if !number.is_empty() || text.is_empty() {
+        return None;
+    }
+
+    Some(Duration::from_secs(secs))
+}
+
+/// Argument Error.
+///
+/// Why an argument couldn't be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArgError {
+    /// The argument wasn't given.
+    Missing,
+
+    /// The argument given is invalid.
+    Invalid(String),
+
+    /// An argument was given after the last one.
+    Unexpected(String),
+}
+
+/// Usage Error.
+///
+/// The arguments of a command couldn't be parsed, see [`FromCommandArgs`].
+///
+/// Replied to the message, with the command usage, instead of running the handler.
+///
+/// [`FromCommandArgs`]: crate::traits::FromCommandArgs
+#[derive(Clone, Debug)]
+pub struct UsageError {
+    usage: String,
+    name: Option<&'static str>,
+    error: ArgError,
+}
+
+impl UsageError {
+    /// Construct a new usage error.
+    ///
+    /// Receives the parsed command, the name of the argument, if any, and its error.
+    pub fn new<T: FromCommandArgs>(
+        args: &CommandArgs,
+        name: Option<&'static str>,
+        error: ArgError,
+    ) -> Self {
+        let mut usage = match args.prefix() {
+            Some(prefix) => format!("{}{}", prefix, args.command()),
+            None => args.command().to_string(),
+        };
+
+        let args_usage = T::usage();
+        if !args_usage.is_empty() {
+            usage.push(' ');
+            usage.push_str(&args_usage);
+        }
+
+        Self { usage, name, error }
+    }
+
+    /// Get the command usage, as `/ban <user> [duration] <reason...>`.
+    pub fn usage(&self) -> &str {
+        &self.usage
+    }
+
+    /// Get the name of the argument, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.name
+    }
+
+    /// Get the argument error.
+    pub fn error(&self) -> &ArgError {
+        &self.error
+    }
+}
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = self.name.unwrap_or("argument");
+
+        match &self.error {
+            ArgError::Missing => write!(f, "Missing the `{}`.", name)?,
+            ArgError::Invalid(arg) => write!(f, "Invalid `{}`: `{}`.", name, arg)?,
+            ArgError::Unexpected(arg) => write!(f, "Unexpected argument: `{}`.", arg)?,
+        }
+
+        write!(f, "\nUsage: {}", self.usage)
+    }
+}
+
+impl StdError for UsageError {}
+
+/// Command information.
+///
+/// The metadata of a [`CommandFilter`], used to generate the [`Help`].
+///
+/// [`CommandFilter`]: crate::filters::CommandFilter
+/// [`Help`]: crate::Help
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommandInfo {
+    prefixes: Vec<char>,
+    name: String,
+    aliases: Vec<String>,
+    description: Option<String>,
+    descriptions: Vec<(String, String)>,
+    usage: Option<String>,
+    category: Option<String>,
+    scopes: Vec<CommandScope>,
+    hidden: bool,
+}
+
+impl CommandInfo {
+    pub(crate) fn new(prefixes: &str, name: String) -> Self {
+        Self {
+            prefixes: prefixes.chars().collect(),
+            name,
+            aliases: Vec::new(),
+            description: None,
+            descriptions: Vec::new(),
+            usage: None,
+            category: None,
+            scopes: Vec::new(),
+            hidden: false,
+        }
+    }
+
+    /// Get the first prefix of the command, if it has prefixes.
+    pub fn prefix(&self) -> Option<char> {
+        self.prefixes.first().copied()
+    }
+
+    /// Get the prefixes of the command.
+    pub fn prefixes(&self) -> &[char] {
+        &self.prefixes
+    }
+
+    /// Get the command name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the command aliases.
+    pub fn aliases(&self) -> &[String] {
+        &self.aliases
+    }
+
+    /// Get the command description.
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// Get the command description in `language`, falling back to the default one.
+    pub fn description_in(&self, language: &str) -> Option<&str> {
+        self.descriptions
+            .iter()
+            .find(|(lang, _)| lang == language)
+            .map(|(_, description)| description.as_str())
+            .or(self.description())
+    }
+
+    /// Get the languages the command is described in, besides the default one.
+    pub fn languages(&self) -> impl Iterator<Item = &str> {
+        self.descriptions
+            .iter()
+            .map(|(language, _)| language.as_str())
+    }
+
+    /// Get the arguments usage, as `<user> [duration] <reason...>`.
+    pub fn usage(&self) -> Option<&str> {
+        self.usage.as_deref()
+    }
+
+    /// Get the command category.
+    pub fn category(&self) -> Option<&str> {
+        self.category.as_deref()
+    }
+
+    /// Get the scopes of the command in the bot command menu.
+    ///
+    /// Empty if only in the default one.
+    pub fn scopes(&self) -> &[CommandScope] {
+        &self.scopes
+    }
+
+    /// Checks if the command is hidden from the help and the bot command menu.
+    pub fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+
+    /// Checks if the command is called `name`, or has it as alias.
+    pub fn is_named(&self, name: &str) -> bool {
+        self.name == name || self.aliases.iter().any(|alias| alias == name)
+    }
+
+    /// Get the command as `/name`.
+    pub fn command(&self) -> String {
+        match self.prefix() {
+            Some(prefix) => format!("{}{}", prefix, self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    pub(crate) fn push_alias(&mut self, alias: String) {
+        self.aliases.push(alias);
+    }
+
+    pub(crate) fn set_description(&mut self, description: String) {
+        self.description = Some(description);
+    }
+
+    pub(crate) fn push_description(&mut self, language: String, description: String) {
+        self.descriptions.retain(|(lang, _)| *lang != language);
+        self.descriptions.push((language, description));
+    }
+
+    pub(crate) fn push_scope(&mut self, scope: CommandScope) {
+        if !self.scopes.contains(&scope) {
+            self.scopes.push(scope);
+        }
+    }
+
+    pub(crate) fn set_usage(&mut self, usage: String) {
+        self.usage = Some(usage);
+    }
+
+    pub(crate) fn set_category(&mut self, category: String) {
+        self.category = Some(category);
+    }
+
+    pub(crate) fn set_hidden(&mut self, hidden: bool) {
+        self.hidden = hidden;
+    }
+}
+
+/// Command Scope.
+///
+/// Who sees a command in the bot command menu.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CommandScope {
+    /// Everyone, unless a narrower scope applies.
+    #[default]
+    Default,
+
+    /// All the private chats.
+    PrivateChats,
+
+    /// All the group chats.
+    GroupChats,
+
+    /// All the group administrators.
+    GroupAdmins,
+}
+
+impl CommandScope {
+    /// Checks if the commands of `scope` are also shown in this one.
+    ///
+    /// As the narrowest scope is shown, every one includes [`CommandScope::Default`],
+    /// And [`CommandScope::GroupAdmins`] includes [`CommandScope::GroupChats`].
+    pub fn includes(self, scope: CommandScope) -> bool {
+        match (self, scope) {
+            (_, CommandScope::Default) => true,
+            (CommandScope::GroupAdmins, CommandScope::GroupChats) => true,
+            (this, scope) => this == scope,
+        }
+    }
+}
+
+impl From<CommandScope> for tl::enums::BotCommandScope {
+    fn from(scope: CommandScope) -> Self {
+        match scope {
+            CommandScope::Default => tl::types::BotCommandScopeDefault {}.into(),
+            CommandScope::PrivateChats => tl::types::BotCommandScopeUsers {}.into(),
+            CommandScope::GroupChats => tl::types::BotCommandScopeChats {}.into(),
+            CommandScope::GroupAdmins => tl::types::BotCommandScopeChatAdmins {}.into(),
+        }
+    }
+}
+
+/// The matcher of a command, built once by the [`CommandFilter`].
+///
+/// [`CommandFilter`]: crate::filters::CommandFilter
+#[derive(Clone)]
+pub(crate) struct Matcher {
+    prefixes: Vec<char>,
+    names: Vec<String>,
+    ignore_case: bool,
+}
+
+impl Matcher {
+    pub(crate) fn new(prefixes: &str, command: String) -> Self {
+        Self {
+            prefixes: prefixes.chars().collect(),
+            names: vec![command],
+            ignore_case: false,
+        }
+    }
+
+    /// Attach a new name to the command.
+    pub(crate) fn push_alias(&mut self, alias: String) {
+        self.names.push(alias);
+    }
+
+    pub(crate) fn set_ignore_case(&mut self, ignore_case: bool) {
+        self.ignore_case = ignore_case;
+    }
+
+    /// Parse the `text`.
+    ///
+    /// Returns `None` if it isn't the command, the mention isn't checked.
+    pub(crate) fn parse(&self, text: &str) -> Option<CommandArgs> {
+        let text = text.trim_start();
+
+        let (prefix, text) = if self.prefixes.is_empty() {
+            (None, text)
+        } else {
+            let prefix = text.chars().next()?;
+            if !self.prefixes.contains(&prefix) {
+                return None;
+            }
+
+            (Some(prefix), &text[prefix.len_utf8()..])
+        };
+
+        let end = text.find(char::is_whitespace).unwrap_or(text.len());
+        let (word, raw) = text.split_at(end);
+
+        let (command, mention) = match word.split_once('@') {
+            Some((command, mention)) => (command, Some(mention)),
+            None => (word, None),
+        };
+        if !self.names.iter().any(|name| match self.ignore_case {
+            true => name.eq_ignore_ascii_case(command),
+            false => name == command,
+        }) {
+            return None;
+        }
+
+        let raw = raw.trim();
+        let (offsets, args) = split_args(raw).into_iter().unzip();
+
+        Some(CommandArgs {
+            prefix,
+            command: command.to_string(),
+            mention: mention.map(String::from),
+            raw: raw.to_string(),
+            args,
+            offsets,
+        })
+    }
+}
+
+/// Split the `text` into arguments, like a shell does.
+///
+/// The arguments are separated by whitespaces, unless inside quotes, `"` or `'`,
+/// Which only start a quoted argument at its beginning, so `don't` is kept as is.
+///
+/// A backslash escapes the next character, an unclosed quote goes until the end.
+///
+/// Returns each argument with the position where it starts.
+pub(crate) fn split_args(text: &str) -> Vec<(usize, String)> {
+    let mut args = Vec::new();
+    let mut chars = text.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let mut arg = String::new();
+
+        let quote = matches!(c, '"' | '\'').then_some(c);
+        if quote.is_some() {
+            chars.next();
+        }
+
+        while let Some((_, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    if let Some((_, c)) = chars.next() {
+                        arg.push(c);
+                    }
+                }
+                c if Some(c) == quote => break,
+                c if quote.is_none() && c.is_whitespace() => break,
+                c => arg.push(c),
+            }
+        }
+
+        args.push((start, arg));
+    }
+
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(text: &str) -> Vec<String> {
+        split_args(text).into_iter().map(|(_, arg)| arg).collect()
+    }
+
+    #[test]
+    fn split_args_by_whitespace() {
+        assert_eq!(split("  a b\n\tc  "), vec!["a", "b", "c"]);
+        assert!(split("   ").is_empty());
+    }
+
+    #[test]
+    fn split_args_with_quotes() {
+        assert_eq!(
+            split(r#"@foo "two words" 'single quoted' don't"#),
+            vec!["@foo", "two words", "single quoted", "don't"]
+        );
+        assert_eq!(split(r#""" "unclosed quote"#), vec!["", "unclosed quote"]);
+    }
+
+    #[test]
+    fn split_args_with_escapes() {
+        assert_eq!(
+            split(r#"a\ b "say \"hi\"" c\\"#),
+            vec!["a b", r#"say "hi""#, r"c\"]
+        );
+        assert_eq!(split(r"trailing\"), vec!["trailing"]);
+    }
+
+    #[test]
+    fn rest_keeps_raw_text() {
+        let args = Matcher::new("/", "ban".to_string())
+            .parse("/ban @foo  \"spam\" here\nand\\there ")
+            .unwrap();
+
+        let mut pending = PendingArgs::new(&args);
+        assert_eq!(pending.pop_front().as_deref(), Some("@foo"));
+        assert_eq!(
+            Rest::from_command_arg(&mut pending),
+            Ok(Rest("\"spam\" here\nand\\there".to_string()))
+        );
+        assert!(pending.is_empty());
+        assert_eq!(Rest::from_command_arg(&mut pending), Err(ArgError::Missing));
+    }
+
+    #[test]
+    fn parse_command() {
+        let matcher = Matcher::new("/!", "ban".to_string());
+
+        let args = matcher.parse("/ban @foo 1h").unwrap();
+        assert_eq!(args.prefix(), Some('/'));
+        assert_eq!(args.command(), "ban");
+        assert_eq!(args.mention(), None);
+        assert_eq!(args.raw(), "@foo 1h");
+        assert_eq!(args.args(), ["@foo", "1h"]);
+
+        assert_eq!(matcher.parse("!ban").unwrap().prefix(), Some('!'));
+        assert!(matcher.parse(".ban").is_none());
+        assert!(matcher.parse("/banned").is_none());
+        assert!(matcher.parse("/unban").is_none());
+        assert!(matcher.parse("ban").is_none());
+        assert!(matcher.parse("").is_none());
+    }
+
+    #[test]
+    fn parse_mention() {
+        let matcher = Matcher::new("/", "ban".to_string());
+
+        let args = matcher.parse("/ban@MyBot @foo").unwrap();
+        assert_eq!(args.command(), "ban");
+        assert_eq!(args.mention(), Some("MyBot"));
+        assert_eq!(args.args(), ["@foo"]);
+
+        assert!(matcher.parse("/unban@MyBot").is_none());
+    }
+
+    #[test]
+    fn parse_ignore_case_and_aliases() {
+        let mut matcher = Matcher::new("/", "ban".to_string());
+        matcher.push_alias("kick".to_string());
+
+        assert!(matcher.parse("/kick").is_some());
+        assert!(matcher.parse("/BAN").is_none());
+
+        matcher.set_ignore_case(true);
+        assert_eq!(matcher.parse("/BAN").unwrap().command(), "BAN");
+        assert!(matcher.parse("/Kick@MyBot").is_some());
+    }
+
+    #[test]
+    fn parse_without_prefixes() {
+        let matcher = Matcher::new("", "ban".to_string());
+
+        assert_eq!(matcher.parse("  ban now").unwrap().args(), ["now"]);
+        assert_eq!(matcher.parse("ban").unwrap().prefix(), None);
+        assert!(matcher.parse("/ban").is_none());
+    }
+}
@@ -0,0 +1,95 @@
+// Copyright (C) 2024 AndrielFR
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::{any::TypeId, collections::HashMap};
+
+use downcast_rs::{impl_downcast, DowncastSync};
+
+/// Context.
+///
+/// Created for each update and dropped after it, stores values indexed by
+/// Their type.
+///
+/// Lets the filters, middlewares and handlers pass values to each other, like
+/// The resolved user or its language.
+///
+/// The values must be `Clone`, as the context is restored when a filter doesn't
+/// Pass, undoing what it changed. Wrap the others in an `Arc`.
+#[derive(Default)]
+pub struct Context {
+    values: HashMap<TypeId, Box<dyn Value>>,
+}
+
+impl Context {
+    /// Construct a new empty context.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert a value.
+    ///
+    /// Returns the previous value of the same type, if any.
+    pub fn insert<T: Clone + Send + Sync + 'static>(&mut self, value: T) -> Option<T> {
+        self.values
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|value| value.downcast().ok())
+            .map(|value| *value)
+    }
+
+    /// Get a value.
+    pub fn get<T: Clone + Send + Sync + 'static>(&self) -> Option<&T> {
+        self.values
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+    }
+
+    /// Get a mutable value.
+    pub fn get_mut<T: Clone + Send + Sync + 'static>(&mut self) -> Option<&mut T> {
+        self.values
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_mut())
+    }
+
+    /// Remove a value.
+    pub fn remove<T: Clone + Send + Sync + 'static>(&mut self) -> Option<T> {
+        self.values
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast().ok())
+            .map(|value| *value)
+    }
+
+    /// Checks if there is a value of type `T`.
+    pub fn contains<T: Clone + Send + Sync + 'static>(&self) -> bool {
+        self.values.contains_key(&TypeId::of::<T>())
+    }
+}
+
+impl Clone for Context {
+    fn clone(&self) -> Self {
+        Self {
+            values: self
+                .values
+                .iter()
+                .map(|(type_id, value)| (*type_id, (**value).clone_value()))
+                .collect(),
+        }
+    }
+}
+
+/// A value of the context.
+trait Value: DowncastSync {
+    fn clone_value(&self) -> Box<dyn Value>;
+}
+
+impl_downcast!(sync Value);
+
+impl<T: Clone + Send + Sync + 'static> Value for T {
+    fn clone_value(&self) -> Box<dyn Value> {
+        Box::new(self.clone())
+    }
+}
@@ -6,41 +6,471 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use crate::traits::Module;
+use std::{
+    any::{type_name, TypeId},
+    collections::HashMap,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use crate::{
+    traits::{FromData, Module},
+    Error, Result,
+};
 
 /// Data.
 ///
-/// Actually, it just stores the modules.
+/// Stores the modules, indexed by their type.
+///
+/// The modules are shared, not copied, between the updates and the routers,
+/// So their state persists.
+///
+/// A router sees its own modules and, by reference, the ones of its parent
+/// Routers and of the dispatcher. Its own modules override the inherited ones
+/// Of the same type and are not visible to the parents.
 #[derive(Clone, Default)]
 pub struct Data {
-    pub(crate) modules: Vec<Box<dyn Module>>,
+    index: HashMap<TypeId, usize>,
+    modules: Vec<Arc<dyn Module>>,
+    providers: Vec<Provider>,
+    parent: Option<Arc<Data>>,
+}
+
+/// A module built from the data at startup.
+#[derive(Clone)]
+struct Provider {
+    type_id: TypeId,
+    name: &'static str,
+    dependencies: fn() -> Vec<Dependency>,
+    build: fn(&Data) -> Result<Arc<dyn Module>>,
 }
 
 impl Data {
-    /// Get a copy of the modules.
-    pub fn modules(&self) -> Vec<Box<dyn Module>> {
+    /// Construct a new data which inherits the modules of `parent`.
+    pub(crate) fn scoped(parent: Arc<Data>) -> Self {
+        Self {
+            parent: Some(parent),
+            ..Default::default()
+        }
+    }
+
+    /// Get the modules, the inherited ones included, unless overridden.
+    pub fn modules(&self) -> Vec<Arc<dyn Module>> {
+        let mut modules = self.own_modules();
+
+        if let Some(parent) = self.parent.as_ref() {
+            modules.extend(
+                parent
+                    .modules()
+                    .into_iter()
+                    .filter(|module| !self.index.contains_key(&(**module).as_any().type_id())),
+            );
+        }
+
+        modules
+    }
+
+    /// Get the modules attached to this data, in the order they were added.
+    pub(crate) fn own_modules(&self) -> Vec<Arc<dyn Module>> {
         self.modules.clone()
     }
 
-    /// Attach a new boxed module.
-    pub(crate) fn push_module(&mut self, module: Box<dyn Module>) {
+    /// Attach a new module.
+    ///
+    /// # Panics
+    ///
+    /// If a module of the same type was already attached.
+    pub(crate) fn push_module<M: Module>(&mut self, module: M) {
+        self.push_entry(TypeId::of::<M>(), type_name::<M>(), Arc::new(module));
+    }
+
+    /// Attach a new module built at startup.
+    ///
+    /// # Panics
+    ///
+    /// If a module of the same type was already attached.
+    pub(crate) fn push_provider<M: FromData>(&mut self) {
+        let type_id = TypeId::of::<M>();
+        let name = type_name::<M>();
+
+        if self.is_added(&type_id) {
+            panic!("the module `{}` was already added", name);
+        }
+
+        self.providers.push(Provider {
+            type_id,
+            name,
+            dependencies: M::dependencies,
+            build: build::<M>,
+        });
+    }
+
+    fn push_entry(&mut self, type_id: TypeId, name: &'static str, module: Arc<dyn Module>) {
+        if self.is_added(&type_id) {
+            panic!("the module `{}` was already added", name);
+        }
+
+        self.index.insert(type_id, self.modules.len());
         self.modules.push(module);
     }
 
-    /// Get a module and downcast it.
+    /// Checks if a module of the type `type_id` was attached, built or not.
+    fn is_added(&self, type_id: &TypeId) -> bool {
+        self.index.contains_key(type_id)
+            || self
+                .providers
+                .iter()
+                .any(|provider| &provider.type_id == type_id)
+    }
+
+    /// Build the modules attached with `push_provider`.
+    ///
+    /// Each one is built after its dependencies, fails if one of them is missing or
+    /// If they depend on each other.
+    pub(crate) fn resolve(&mut self) -> Result<()> {
+        let providers = std::mem::take(&mut self.providers);
+
+        let mut path = Vec::new();
+        for i in 0..providers.len() {
+            self.provide(&providers, i, &mut path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Build the `i`th provider and, before, its dependencies.
+    ///
+    /// `path` holds the providers being built, to find the cycles.
+    fn provide(&mut self, providers: &[Provider], i: usize, path: &mut Vec<usize>) -> Result<()> {
+        let provider = &providers[i];
+
+        if self.index.contains_key(&provider.type_id) {
+            return Ok(());
+        }
+
+        if let Some(start) = path.iter().position(|&j| j == i) {
+            let cycle = path[start..]
+                .iter()
+                .chain([&i])
+                .map(|&j| format!("`{}`", providers[j].name))
+                .collect::<Vec<_>>()
+                .join(" -> ");
+
+            return Err(Error::other(format!(
+                "the modules depend on each other: {}",
+                cycle
+            )));
+        }
+
+        path.push(i);
+
+        for dependency in (provider.dependencies)() {
+            match providers
+                .iter()
+                .position(|provider| provider.type_id == dependency.type_id)
+            {
+                Some(j) => self.provide(providers, j, path)?,
+                None if self.contains(&dependency.type_id) => {}
+                None => {
+                    return Err(Error::other(format!(
+                        "the module `{}` needs `{}`, which was not added",
+                        provider.name, dependency.name
+                    )))
+                }
+            }
+        }
+
+        path.pop();
+
+        let module = (provider.build)(self).map_err(|e| {
+            Error::other(format!(
+                "failed to build the module `{}`: {}",
+                provider.name, e
+            ))
+        })?;
+        self.push_entry(provider.type_id, provider.name, module);
+
+        Ok(())
+    }
+
+    /// Inherit, by reference, the modules of `parent`.
+    ///
+    /// The attached modules override the `parent`'s ones of the same type.
+    pub(crate) fn set_parent(&mut self, parent: Arc<Data>) {
+        self.parent = Some(parent);
+    }
+
+    /// Checks if a module of the type `type_id` is visible.
+    fn contains(&self, type_id: &TypeId) -> bool {
+        self.index.contains_key(type_id)
+            || self
+                .parent
+                .as_ref()
+                .is_some_and(|parent| parent.contains(type_id))
+    }
+
+    /// Get a module.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use grammers_client::{Client, Update};
+    /// # use grammers_friendly::{traits::Module, Data, Result};
+    /// # struct T;
+    /// # impl Module for T {}
+    /// # async fn f(client: &mut Client, update: &mut Update, data: &mut Data) -> Result {
+    /// let t = data.get::<T>().unwrap();
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get<M: Module>(&self) -> Option<Arc<M>> {
+        match self.index.get(&TypeId::of::<M>()) {
+            Some(&i) => Arc::clone(&self.modules[i]).downcast_arc::<M>().ok(),
+            None => self.parent.as_ref().and_then(|parent| parent.get::<M>()),
+        }
+    }
+
+    /// Get a copy of a module.
+    ///
+    /// Changes to the copy aren't shared, unlike the module got with [`Data::get`].
+    #[deprecated(note = "use `Data::get` instead")]
+    pub fn get_module<M: Module + Clone>(&self) -> Option<Box<M>> {
+        self.get::<M>().map(|module| Box::new(M::clone(&module)))
+    }
+
+    /// Read a state attached with `add_state`.
+    ///
+    /// Returns `None` if there is no state of type `S`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use grammers_client::{Client, Update};
+    /// # use grammers_friendly::{Data, Result};
+    /// # async fn f(client: &mut Client, update: &mut Update, data: &mut Data) -> Result {
+    /// let count = data.with::<u64, _>(|count| *count).unwrap();
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with<S, R>(&self, f: impl FnOnce(&S) -> R) -> Option<R>
+    where
+        S: Send + Sync + 'static,
+    {
+        self.get::<State<S>>().map(|state| state.read(f))
+    }
+
+    /// Change a state attached with `add_state`.
+    ///
+    /// Returns `None` if there is no state of type `S`.
     ///
     /// # Example
     ///
     /// ```
-    /// # async fn f(client: &mut grammers_client::Client, update: &mut grammers_client::Update, data: &mut crate:: data) -> Result<(), Box<dyn std::error::Error>> {
-    /// let t = data.get_modules::<T>().unwrap();
+    /// # use grammers_client::{Client, Update};
+    /// # use grammers_friendly::{Data, Result};
+    /// # async fn f(client: &mut Client, update: &mut Update, data: &mut Data) -> Result {
+    /// data.with_mut::<u64, _>(|count| *count += 1);
     ///
-    /// # Ok()
+    /// # Ok(())
     /// # }
     /// ```
-    pub fn get_module<M: Module>(&self) -> Option<Box<M>> {
-        self.modules
-            .iter()
-            .find_map(|module| Box::clone(module).downcast::<M>().ok())
+    pub fn with_mut<S, R>(&self, f: impl FnOnce(&mut S) -> R) -> Option<R>
+    where
+        S: Send + Sync + 'static,
+    {
+        self.get::<State<S>>().map(|state| state.write(f))
+    }
+}
+
+/// A Dependency.
+///
+/// A module needed by a [`FromData`] module.
+///
+/// [`FromData`]: crate::traits::FromData
+#[derive(Clone, Copy, Debug)]
+pub struct Dependency {
+    type_id: TypeId,
+    name: &'static str,
+}
+
+impl Dependency {
+    /// The module of type `M`.
+    pub fn of<M: Module>() -> Self {
+        Self {
+            type_id: TypeId::of::<M>(),
+            name: type_name::<M>(),
+        }
+    }
+
+    /// Get the module name.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+fn build<M: FromData>(data: &Data) -> Result<Arc<dyn Module>> {
+    Ok(Arc::new(M::from_data(data)?))
+}
+
+/// A State.
+///
+/// A module which wraps any value behind a lock,
+/// Read and changed through [`Data::with`] and [`Data::with_mut`].
+///
+/// [`Data::with`]: crate::Data::with
+/// [`Data::with_mut`]: crate::Data::with_mut
+pub struct State<S> {
+    value: RwLock<S>,
+}
+
+impl<S: Send + Sync + 'static> State<S> {
+    pub fn new(value: S) -> Self {
+        Self {
+            value: RwLock::new(value),
+        }
+    }
+
+    /// Read the value.
+    pub fn read<R>(&self, f: impl FnOnce(&S) -> R) -> R {
+        f(&self.value.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Change the value.
+    pub fn write<R>(&self, f: impl FnOnce(&mut S) -> R) -> R {
+        f(&mut self.value.write().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+impl<S: Send + Sync + 'static> Module for State<S> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Config(u32);
+
+    impl Module for Config {}
+
+    /// Needs the config.
+    struct Db(u32);
+
+    impl Module for Db {}
+
+    impl FromData for Db {
+        fn dependencies() -> Vec<Dependency> {
+            vec![Dependency::of::<Config>()]
+        }
+
+        fn from_data(data: &Data) -> Result<Self> {
+            let config = data.get::<Config>().ok_or("missing config")?;
+            Ok(Self(config.0 + 1))
+        }
+    }
+
+    /// Needs the database.
+    struct Cache(u32);
+
+    impl Module for Cache {}
+
+    impl FromData for Cache {
+        fn dependencies() -> Vec<Dependency> {
+            vec![Dependency::of::<Db>()]
+        }
+
+        fn from_data(data: &Data) -> Result<Self> {
+            let db = data.get::<Db>().ok_or("missing database")?;
+            Ok(Self(db.0 + 1))
+        }
+    }
+
+    /// Needs the pong.
+    struct Ping;
+
+    impl Module for Ping {}
+
+    impl FromData for Ping {
+        fn dependencies() -> Vec<Dependency> {
+            vec![Dependency::of::<Pong>()]
+        }
+
+        fn from_data(_: &Data) -> Result<Self> {
+            Ok(Self)
+        }
+    }
+
+    /// Needs the ping.
+    struct Pong;
+
+    impl Module for Pong {}
+
+    impl FromData for Pong {
+        fn dependencies() -> Vec<Dependency> {
+            vec![Dependency::of::<Ping>()]
+        }
+
+        fn from_data(_: &Data) -> Result<Self> {
+            Ok(Self)
+        }
+    }
+
+    #[test]
+    fn resolve_in_dependency_order() {
+        let mut data = Data::default();
+        data.push_provider::<Cache>();
+        data.push_provider::<Db>();
+        data.push_module(Config(1));
+
+        data.resolve().unwrap();
+
+        assert_eq!(data.get::<Db>().unwrap().0, 2);
+        assert_eq!(data.get::<Cache>().unwrap().0, 3);
+    }
+
+    #[test]
+    fn resolve_fails_on_cycles() {
+        let mut data = Data::default();
+        data.push_provider::<Ping>();
+        data.push_provider::<Pong>();
+
+        let e = data.resolve().unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "the modules depend on each other: `{0}` -> `{1}` -> `{0}`",
+                type_name::<Ping>(),
+                type_name::<Pong>()
+            )
+        );
+    }
+
+    #[test]
+    fn resolve_fails_on_missing_dependencies() {
+        let mut data = Data::default();
+        data.push_provider::<Cache>();
+        data.push_provider::<Db>();
+
+        let e = data.resolve().unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "the module `{}` needs `{}`, which was not added",
+                type_name::<Db>(),
+                type_name::<Config>()
+            )
+        );
+    }
+
+    #[test]
+    fn resolve_with_parent_dependencies() {
+        let mut parent = Data::default();
+        parent.push_module(Config(1));
+
+        let mut data = Data::scoped(Arc::new(parent));
+        data.push_provider::<Db>();
+
+        data.resolve().unwrap();
+
+        assert_eq!(data.get::<Db>().unwrap().0, 2);
     }
 }
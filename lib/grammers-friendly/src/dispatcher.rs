@@ -6,27 +6,96 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::{pin::pin, sync::Arc};
+use std::{
+    collections::HashMap,
+    pin::pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use futures_util::future::{select, Either};
-use grammers_client::{types::Chat, Client};
-use tokio::sync::Mutex;
+use grammers_client::{
+    grammers_tl_types::functions::bots::SetBotCommands, types::Chat, Client, Update,
+};
+use tokio::{
+    sync::{
+        mpsc::{self, error::TrySendError},
+        OwnedSemaphorePermit, Semaphore,
+    },
+    task::{JoinHandle, JoinSet},
+};
 
 use crate::{
-    traits::{GetSender, Module},
-    Data, Middleware, Router,
+    middleware::{Endpoint, Middlewares},
+    sources::{self, ClientUpdateSource, UpdateErrorCallback},
+    traits::{
+        ErrorHandlerCallback, FromData, GetChat, GetSender, Module, ShutdownHook, UpdateSource,
+    },
+    utils, Account, CommandInfo, Context, Data, DispatcherError, Error, Help, Metrics, Middleware,
+    Next, RetryPolicy, Router, ShutdownHandle, State, StopReason, Veto,
 };
 
+/// Max of updates that can be handled at the same time.
+const MAX_CONCURRENT_UPDATES: usize = u32::MAX as usize >> 3;
+
 /// The main dispatcher.
 ///
 /// Receives `modules`, `middlewares` and `routers`.
-#[derive(Default)]
 pub struct Dispatcher {
     data: Data,
-    middlewares: Vec<Arc<Mutex<Middleware>>>,
+    middlewares: Middlewares,
+    outer_middlewares: Middlewares,
     routers: Vec<Router>,
 
     ignore_updates_from_self: bool,
+    max_concurrent_updates: usize,
+    strategy: DispatchStrategy,
+    metrics: Arc<Metrics>,
+
+    shutdown: ShutdownHandle,
+    shutdown_hooks: Vec<Box<dyn ShutdownHook>>,
+    shutdown_timeout: Option<Duration>,
+    handle_ctrl_c: bool,
+
+    retry_policy: RetryPolicy,
+    on_update_error: Option<Arc<UpdateErrorCallback>>,
+
+    set_bot_commands: bool,
+
+    error_handler: Option<Arc<dyn ErrorHandlerCallback>>,
+    veto: Veto,
+}
+
+impl Default for Dispatcher {
+    fn default() -> Self {
+        Self {
+            data: Data::default(),
+            middlewares: Middlewares::default(),
+            outer_middlewares: Middlewares::default(),
+            routers: Vec::new(),
+
+            ignore_updates_from_self: false,
+            max_concurrent_updates: 1,
+            strategy: DispatchStrategy::default(),
+            metrics: Arc::new(Metrics::default()),
+
+            shutdown: ShutdownHandle::new(),
+            shutdown_hooks: Vec::new(),
+            shutdown_timeout: None,
+            handle_ctrl_c: true,
+
+            retry_policy: RetryPolicy::default(),
+            on_update_error: None,
+
+            set_bot_commands: false,
+
+            error_handler: None,
+            veto: Veto::default(),
+        }
+    }
 }
 
 impl Dispatcher {
@@ -36,17 +105,78 @@ impl Dispatcher {
     ///
     /// Has no effect if added after sub-routers.
     pub fn add_middleware(mut self, middleware: Middleware) -> Self {
-        self.middlewares.push(Arc::new(Mutex::new(middleware)));
+        self.middlewares.push(middleware);
+        self
+    }
+
+    /// Attach a new outer middleware to the dispatcher.
+    ///
+    /// Which will be runned before, after or around the routing of every update,
+    /// Before any `router`, unlike the ones of `add_middleware`.
+    ///
+    /// A before-type one may veto the update, see [`Veto`], an after-type one
+    /// Is runned even if no `router` handled it.
+    ///
+    /// [`Veto`]: crate::Veto
+    pub fn add_outer_middleware(mut self, middleware: Middleware) -> Self {
+        self.outer_middlewares.push(middleware);
         self
     }
 
     /// Attach a new module to the dispatcher.
     ///
-    /// Which will be sent a mutable reference for each `middleware` and `handler`.
+    /// Which will be shared between each `middleware` and `handler` of all routers.
     ///
-    /// Has no effect if added after sub-routers.
+    /// # Panics
+    ///
+    /// If a module of the same type was already added.
     pub fn add_module<M: Module>(mut self, module: M) -> Self {
-        self.data.push_module(Box::new(module));
+        self.data.push_module(module);
+        self
+    }
+
+    /// Attach a new module built at startup to the dispatcher.
+    ///
+    /// See [`FromData`].
+    ///
+    /// # Panics
+    ///
+    /// If a module of the same type was already added.
+    ///
+    /// [`FromData`]: crate::traits::FromData
+    pub fn provide<M: FromData>(mut self) -> Self {
+        self.data.push_provider::<M>();
+        self
+    }
+
+    /// Attach a new state to the dispatcher.
+    ///
+    /// Which can be read and changed by each `middleware` and `handler`
+    /// Through [`Data::with`] and [`Data::with_mut`].
+    ///
+    /// [`Data::with`]: crate::Data::with
+    /// [`Data::with_mut`]: crate::Data::with_mut
+    pub fn add_state<S: Send + Sync + 'static>(self, state: S) -> Self {
+        self.add_module(State::new(state))
+    }
+
+    /// Set the global error handler.
+    ///
+    /// Receives `Fn(Error, &mut Client, &mut Update, &mut Data, &mut Context) -> Flow`,
+    /// Which will be runned when a `handler` fails in a router without its own
+    /// Error handler, deciding if the update keeps being propagated.
+    pub fn on_error<E: ErrorHandlerCallback>(mut self, error_handler: E) -> Self {
+        self.error_handler = Some(Arc::new(error_handler));
+        self
+    }
+
+    /// Set what the routers do when a before-type middleware vetoes an update.
+    ///
+    /// Used by the routers without their own, see [`Router::on_veto`].
+    ///
+    /// [`Router::on_veto`]: crate::Router::on_veto
+    pub fn on_veto(mut self, veto: Veto) -> Self {
+        self.veto = veto;
         self
     }
 
@@ -54,13 +184,7 @@ impl Dispatcher {
     ///
     /// Which will be runned after the before `middleware`.
     pub fn add_router(mut self, mut router: Router) -> Self {
-        self.data.modules.iter().for_each(|module| {
-            router.push_module(Box::clone(module));
-        });
-
-        self.middlewares.iter().for_each(|middleware| {
-            router.push_middleware(Arc::clone(middleware));
-        });
+        router.extend_middlewares(&self.middlewares);
 
         router.update_sub_routers();
 
@@ -80,46 +204,773 @@ impl Dispatcher {
         self
     }
 
+    /// Set how many updates can be handled at the same time.
+    ///
+    /// Each update is handled in its own task and the dispatcher stops
+    /// pulling new updates while the limit is reached.
+    ///
+    /// With [`DispatchStrategy::PerChat`] it limits how many chats are handled at
+    /// The same time instead, the updates keep being pulled into the chat queues,
+    /// Unless one is full, see [`QueueOverflow`].
+    ///
+    /// [`DispatchStrategy::PerChat`]: crate::DispatchStrategy::PerChat
+    /// [`QueueOverflow`]: crate::QueueOverflow
+    ///
+    /// `1` -> one update at a time (default).
+    pub fn max_concurrent_updates(mut self, limit: usize) -> Self {
+        self.max_concurrent_updates = limit.clamp(1, MAX_CONCURRENT_UPDATES);
+        self
+    }
+
+    /// Set how the updates are distributed between the tasks.
+    ///
+    /// See [`DispatchStrategy`].
+    ///
+    /// [`DispatchStrategy`]: crate::DispatchStrategy
+    pub fn dispatch_strategy(mut self, strategy: DispatchStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Get the dispatcher metrics.
+    ///
+    /// Can be called before [`Dispatcher::run`] and read while it runs.
+    pub fn metrics(&self) -> Arc<Metrics> {
+        Arc::clone(&self.metrics)
+    }
+
+    /// Get a handle to stop the dispatcher.
+    ///
+    /// Can be called before [`Dispatcher::run`] and used while it runs.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Attach a new shutdown hook to the dispatcher.
+    ///
+    /// Receives an async closure, `Fn(Client) -> Result<...>`,
+    /// Which will be runned after the in-flight updates are drained,
+    /// In the order they were added, once per account.
+    pub fn add_shutdown_hook<H: ShutdownHook>(mut self, hook: H) -> Self {
+        self.shutdown_hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Set how long the dispatcher waits for the in-flight updates when stopping.
+    ///
+    /// The remaining updates keep running detached after the timeout.
+    ///
+    /// By default it waits until all of them finish.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = Some(timeout);
+        self
+    }
+
+    /// Stop the dispatcher when Ctrl-C is received.
+    ///
+    /// `true` -> stop (default).
+    /// `false` -> ignore it, use the [`ShutdownHandle`] instead.
+    ///
+    /// [`ShutdownHandle`]: crate::ShutdownHandle
+    pub fn handle_ctrl_c(mut self, value: bool) -> Self {
+        self.handle_ctrl_c = value;
+        self
+    }
+
+    /// Set how the dispatcher retries when the update source fails.
+    ///
+    /// See [`RetryPolicy`], by default it retries forever.
+    ///
+    /// [`RetryPolicy`]: crate::RetryPolicy
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Set a callback to observe the update source errors.
+    ///
+    /// Receives the error and how many times in a row the source failed,
+    /// Runned before each retry and before giving up.
+    pub fn on_update_error<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&Error, u32) + Send + Sync + 'static,
+    {
+        self.on_update_error = Some(Arc::new(callback));
+        self
+    }
+
+    /// Push the bot command menu to Telegram on startup, see [`Dispatcher::bot_commands`].
+    ///
+    /// Only the bot accounts are updated, each with the commands it handles,
+    /// See [`Dispatcher::commands_for`], and their menus no longer used are reset.
+    ///
+    /// `true` -> push it, failing the startup if it can't.
+    /// `false` -> don't push it (default).
+    pub fn set_bot_commands(mut self, value: bool) -> Self {
+        self.set_bot_commands = value;
+        self
+    }
+
+    /// Get the commands of all the routers, in the order they were added.
+    pub fn commands(&self) -> Vec<CommandInfo> {
+        self.routers.iter().flat_map(Router::commands).collect()
+    }
+
+    /// Get the commands the account `name` handles, in the order they were added.
+    ///
+    /// Same as [`Dispatcher::commands`], without the routers of other accounts.
+    pub fn commands_for(&self, name: &str) -> Vec<CommandInfo> {
+        self.routers
+            .iter()
+            .flat_map(|router| router.commands_for(name))
+            .collect()
+    }
+
+    /// Get the bot command menu of the routers' commands.
+    ///
+    /// One request per scope and language, see [`utils::bot_commands`].
+    ///
+    /// [`utils::bot_commands`]: crate::utils::bot_commands
+    pub fn bot_commands(&self) -> Vec<SetBotCommands> {
+        utils::bot_commands(&self.commands())
+    }
+
+    /// Push the bot command menu of its commands to each bot account.
+    ///
+    /// The menus no longer used are reset first, see [`utils::reset_bot_commands`].
+    ///
+    /// [`utils::reset_bot_commands`]: crate::utils::reset_bot_commands
+    async fn push_bot_commands(&self, accounts: &[Account]) -> Result<(), Error> {
+        for account in accounts.iter() {
+            // The user accounts can't have a command menu.
+            if !account.client().get_me().await?.is_bot() {
+                continue;
+            }
+
+            let commands = self.commands_for(account.name());
+
+            for reset in utils::reset_bot_commands(&commands) {
+                account.client().invoke(&reset).await?;
+            }
+            for request in utils::bot_commands(&commands) {
+                account.client().invoke(&request).await?;
+            }
+        }
+
+        Ok(())
+    }
+
     /// Run the dispatcher.
     ///
     /// Listen to the updates sent by Telegram and distribute them whitin the `routers`.
-    pub async fn run(mut self, client: Client) -> Result<(), Box<dyn std::error::Error>> {
-        loop {
-            let exit = pin!(async { tokio::signal::ctrl_c().await });
-            let update = pin!(async { client.next_update().await });
+    ///
+    /// Starts by building the provided modules, sharing the modules with the routers and
+    /// Running their `on_startup`, the dispatcher's first, then the routers', in the order
+    /// They were added. Any error, as a missing module, aborts the startup, running the
+    /// `on_shutdown` of the modules already started, in reverse order.
+    ///
+    /// The [`Help`] of the routers' commands, per account, is added as module, if there is
+    /// No one, and the bot command menu is pushed, if enabled, after the `on_startup`s.
+    /// Failing to push it also aborts the startup.
+    ///
+    /// Stops on Ctrl-C or through the [`ShutdownHandle`], then waits for the in-flight
+    /// updates and runs the modules' `on_shutdown` and the shutdown hooks before returning.
+    ///
+    /// The update errors are retried following the [`RetryPolicy`].
+    ///
+    /// [`Help`]: crate::Help
+    /// [`ShutdownHandle`]: crate::ShutdownHandle
+    /// [`RetryPolicy`]: crate::RetryPolicy
+    pub async fn run(self, client: Client) -> Result<StopReason, DispatcherError> {
+        self.run_with(ClientUpdateSource::new(client)).await
+    }
+
+    /// Run the dispatcher with many accounts.
+    ///
+    /// Same as [`Dispatcher::run`], but the updates come from all the `clients`,
+    /// Each of them tagged with its [`Account`] name.
+    ///
+    /// The routers, modules and middlewares are shared between the accounts.
+    ///
+    /// Each account is retried on its own following the [`RetryPolicy`], the one
+    /// Which gives up is stopped while the others keep running. Once all of them
+    /// Are stopped, the dispatcher fails with the last error of the ones which gave up.
+    ///
+    /// [`Account`]: crate::Account
+    /// [`RetryPolicy`]: crate::RetryPolicy
+    pub async fn run_accounts<N: Into<String>>(
+        self,
+        clients: impl IntoIterator<Item = (N, Client)>,
+    ) -> Result<StopReason, DispatcherError> {
+        let mut source = sources::accounts(clients).retry_policy(self.retry_policy.clone());
+
+        if let Some(callback) = self.on_update_error.clone() {
+            source = source.on_error(move |e, failures| callback(e, failures));
+        }
+
+        self.run_with(source).await
+    }
+
+    /// Run the dispatcher with a custom update source.
+    ///
+    /// Same as [`Dispatcher::run`], but the updates come from `source`,
+    /// Which also stops the dispatcher when exhausted.
+    ///
+    /// The source errors are retried only if it doesn't retry them itself,
+    /// See [`UpdateSource::retries_errors`].
+    ///
+    /// [`UpdateSource::retries_errors`]: crate::traits::UpdateSource::retries_errors
+    ///
+    /// The lifecycle hooks are runned once per account of the source.
+    pub async fn run_with<S: UpdateSource>(
+        mut self,
+        mut source: S,
+    ) -> Result<StopReason, DispatcherError> {
+        let accounts = source.accounts();
+
+        let mut data = std::mem::take(&mut self.data);
+        if data.get::<Help>().is_none() {
+            let help = accounts
+                .iter()
+                .fold(Help::new(self.commands()), |help, account| {
+                    help.with_account(account.name(), self.commands_for(account.name()))
+                });
+
+            data.push_module(help);
+        }
+        data.resolve().map_err(DispatcherError::Startup)?;
+
+        let data = Arc::new(data);
+        let mut lifecycle = data.own_modules();
+
+        self.data = Data::scoped(Arc::clone(&data));
+
+        for router in self.routers.iter_mut() {
+            router
+                .set_parent_data(&data)
+                .map_err(DispatcherError::Startup)?;
+            lifecycle.extend(router.own_modules());
+        }
+
+        let mut started = Vec::new();
+        for module in lifecycle.iter() {
+            for account in accounts.iter() {
+                if let Err(e) = module.on_startup(account.client()).await {
+                    // Undo the ones already started, in reverse order.
+                    shutdown_modules(started.into_iter().rev()).await;
+                    return Err(DispatcherError::Startup(e));
+                }
 
-            let mut update = match select(exit, update).await {
-                Either::Left(_) => break,
-                Either::Right((u, _)) => u?,
+                started.push((module, account));
+            }
+        }
+
+        if self.set_bot_commands {
+            if let Err(e) = self.push_bot_commands(&accounts).await {
+                shutdown_modules(started.into_iter().rev()).await;
+                return Err(DispatcherError::Startup(e));
+            }
+        }
+
+        let limit = self.max_concurrent_updates;
+        let semaphore = Arc::new(Semaphore::new(limit));
+
+        let ctrl_c = self.handle_ctrl_c.then(|| {
+            let shutdown = self.shutdown.clone();
+
+            tokio::spawn(async move {
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    shutdown.stop(StopReason::CtrlC);
+                }
+            })
+        });
+
+        let mut queues = match self.strategy {
+            DispatchStrategy::Concurrent => None,
+            DispatchStrategy::PerChat {
+                queue_size,
+                overflow,
+            } => Some(ChatQueues::new(queue_size, overflow)),
+        };
+
+        let dispatcher = Arc::new(self);
+        let final_errors = source.retries_errors();
+        let mut failures = 0;
+
+        let outcome = loop {
+            let permit = match queues {
+                Some(_) => None,
+                None => match dispatcher.acquire(&semaphore).await {
+                    Ok(permit) => Some(permit),
+                    Err(reason) => break Ok(reason),
+                },
+            };
+
+            let exit = pin!(dispatcher.shutdown.wait());
+            let update = pin!(source.next_update());
+
+            let result = match select(exit, update).await {
+                Either::Left((reason, _)) => break Ok(reason),
+                Either::Right((result, _)) => result,
             };
 
-            moro::async_scope!(|scope| {
-                let mut client = client.clone();
-                let update = &mut update;
-
-                let routers = &mut self.routers;
-
-                scope.spawn(async move {
-                    if self.ignore_updates_from_self {
-                        if let Some(sender) = update.get_sender() {
-                            if let Chat::User(user) = sender {
-                                if user.is_self() {
-                                    return;
-                                }
-                            }
-                        }
+            let (account, update) = match result {
+                Ok(Some(update)) => {
+                    failures = 0;
+                    update
+                }
+                Ok(None) => break Ok(StopReason::SourceExhausted),
+                Err(e) if final_errors => break Err(DispatcherError::UpdateSource(e)),
+                Err(e) => {
+                    failures += 1;
+
+                    if let Some(callback) = dispatcher.on_update_error.as_ref() {
+                        callback(&e, failures);
                     }
 
-                    for router in routers.iter_mut() {
-                        if router.handle_update(&mut client, update).await {
-                            break;
-                        }
+                    let Some(backoff) = dispatcher.retry_policy.backoff(failures) else {
+                        break Err(DispatcherError::UpdateSource(e));
+                    };
+
+                    log::warn!(
+                        "Error while receiving update, retrying in {:?}: {}",
+                        backoff,
+                        e
+                    );
+
+                    let exit = pin!(dispatcher.shutdown.wait());
+                    let sleep = pin!(tokio::time::sleep(backoff));
+
+                    if let Either::Left((reason, _)) = select(exit, sleep).await {
+                        break Ok(reason);
                     }
-                });
-            })
-            .await;
+
+                    continue;
+                }
+            };
+
+            match queues {
+                Some(ref mut queues) => {
+                    queues.push(&dispatcher, &semaphore, account, update).await;
+                }
+                None => {
+                    let dispatcher = Arc::clone(&dispatcher);
+
+                    tokio::spawn(async move {
+                        dispatcher.handle_update(account, update).await;
+                        drop(permit);
+                    });
+                }
+            }
+        };
+
+        if let Some(ctrl_c) = ctrl_c {
+            ctrl_c.abort();
         }
 
-        Ok(())
+        dispatcher.drain(queues, &semaphore, limit).await;
+
+        shutdown_modules(started).await;
+
+        for hook in dispatcher.shutdown_hooks.iter() {
+            for account in accounts.iter() {
+                if let Err(e) = hook.call(account.client().clone()).await {
+                    log::error!("Error while running shutdown hook: {}", e);
+                }
+            }
+        }
+
+        outcome
+    }
+
+    /// Wait for room to handle a new update.
+    ///
+    /// Gives up if the dispatcher is stopped meanwhile, returning the reason.
+    async fn acquire(
+        &self,
+        semaphore: &Arc<Semaphore>,
+    ) -> Result<OwnedSemaphorePermit, StopReason> {
+        let exit = pin!(self.shutdown.wait());
+        let acquire = pin!(Arc::clone(semaphore).acquire_owned());
+
+        match select(exit, acquire).await {
+            Either::Left((reason, _)) => Err(reason),
+            Either::Right((permit, _)) => Ok(permit.expect("the semaphore is never closed")),
+        }
+    }
+
+    /// Wait for the queued and in-flight updates.
+    ///
+    /// Gives up after the `shutdown_timeout`, if any.
+    async fn drain(&self, queues: Option<ChatQueues>, semaphore: &Semaphore, limit: usize) {
+        let drain = async {
+            if let Some(queues) = queues {
+                queues.close(&self.metrics).await;
+            }
+
+            let _ = semaphore.acquire_many(limit as u32).await;
+        };
+
+        match self.shutdown_timeout {
+            Some(timeout) => {
+                if tokio::time::timeout(timeout, drain).await.is_err() {
+                    log::warn!(
+                        "Shutdown timeout reached with {} updates still in flight",
+                        self.metrics.in_flight()
+                    );
+                }
+            }
+            None => drain.await,
+        }
+    }
+
+    /// Handle a single update.
+    ///
+    /// Distributes it whitin the `routers` until one of them handles it.
+    async fn handle_update(&self, account: Account, update: Update) {
+        self.metrics.in_flight.fetch_add(1, Ordering::Relaxed);
+        self.route_update(account, update).await;
+        self.metrics.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    async fn route_update(&self, account: Account, mut update: Update) {
+        let mut client = account.client().clone();
+
+        if self.ignore_updates_from_self {
+            if let Some(Chat::User(user)) = update.get_sender() {
+                if user.is_self() {
+                    return;
+                }
+            }
+        }
+
+        let mut data = self.data.clone();
+        data.push_module(account.clone());
+
+        let mut context = Context::new();
+
+        let endpoint = Endpoint::Routers {
+            routers: &self.routers,
+            account: &account,
+            error_handler: self.error_handler.as_ref(),
+        };
+
+        if let Err(e) = Next::new(&self.outer_middlewares, self.veto, endpoint)
+            .run(&mut client, &mut update, &mut data, &mut context)
+            .await
+        {
+            log::error!("Error while running outer middleware: {}", e);
+        }
+    }
+}
+
+/// Run the `on_shutdown` of each module, with its account.
+async fn shutdown_modules<'a>(
+    started: impl IntoIterator<Item = (&'a Arc<dyn Module>, &'a Account)>,
+) {
+    for (module, account) in started {
+        if let Err(e) = module.on_shutdown(account.client()).await {
+            log::error!("Error while running module shutdown: {}", e);
+        }
+    }
+}
+
+/// Dispatch Strategy.
+///
+/// Defines how the updates are distributed between the tasks,
+/// Both are limited by [`Dispatcher::max_concurrent_updates`].
+///
+/// [`Dispatcher::max_concurrent_updates`]: crate::Dispatcher::max_concurrent_updates
+#[derive(Clone, Debug, Default)]
+pub enum DispatchStrategy {
+    /// Each update is handled on its own, in no particular order (default).
+    #[default]
+    Concurrent,
+
+    /// Updates from the same chat (or sender, if there is no chat) of the same account are
+    /// Handled in the order they arrived, updates from different chats are handled in parallel.
+    ///
+    /// Each chat can have up to `queue_size` pending updates, when a queue is full the
+    /// `overflow` decides what happens to its new updates, see [`QueueOverflow`].
+    ///
+    /// Each chat takes a slot of [`Dispatcher::max_concurrent_updates`] while handling
+    /// An update, so with the default of `1` the chats are still handled one at a time.
+    ///
+    /// [`Dispatcher::max_concurrent_updates`]: crate::Dispatcher::max_concurrent_updates
+    /// [`QueueOverflow`]: crate::QueueOverflow
+    PerChat {
+        queue_size: usize,
+        overflow: QueueOverflow,
+    },
+}
+
+/// Queue Overflow.
+///
+/// What [`DispatchStrategy::PerChat`] does with the new updates of a full chat queue.
+///
+/// [`DispatchStrategy::PerChat`]: crate::DispatchStrategy::PerChat
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum QueueOverflow {
+    /// Wait for room before pulling new updates, which holds the other chats
+    /// Meanwhile, but keeps all the updates in order (default).
+    #[default]
+    Wait,
+
+    /// Drop the update, without holding the other chats.
+    Drop,
+}
+
+/// The per-chat queues used by [`DispatchStrategy::PerChat`].
+///
+/// Keyed by the account name and the chat id, as each account sees its own chats.
+struct ChatQueues {
+    queue_size: usize,
+    overflow: QueueOverflow,
+    queues: HashMap<(String, i64), ChatQueue>,
+    unkeyed: JoinSet<()>,
+}
+
+/// A single chat queue, served by its own task.
+struct ChatQueue {
+    sender: mpsc::Sender<(Account, Update)>,
+    pending: Arc<AtomicUsize>,
+    worker: JoinHandle<()>,
+}
+
+impl ChatQueues {
+    fn new(queue_size: usize, overflow: QueueOverflow) -> Self {
+        Self {
+            queue_size: queue_size.max(1),
+            overflow,
+            queues: HashMap::new(),
+            unkeyed: JoinSet::new(),
+        }
+    }
+
+    /// Push the update to its chat queue, following the `overflow` if the queue is full.
+    ///
+    /// Updates without chat and sender, as the inline queries, are spawned right away,
+    /// Waiting for room in their own task, as the queues do.
+    async fn push(
+        &mut self,
+        dispatcher: &Arc<Dispatcher>,
+        semaphore: &Arc<Semaphore>,
+        account: Account,
+        update: Update,
+    ) {
+        let metrics = &dispatcher.metrics;
+
+        // Idle queues are dropped, which stops their tasks.
+        self.queues
+            .retain(|_, queue| queue.pending.load(Ordering::Acquire) > 0);
+
+        let key = update
+            .get_chat()
+            .or_else(|| update.get_sender())
+            .map(|chat| (account.name().to_string(), chat.id()));
+
+        let Some(key) = key else {
+            let dispatcher = Arc::clone(dispatcher);
+            let semaphore = Arc::clone(semaphore);
+
+            // Finished ones are dropped, the others are waited by `close`.
+            while self.unkeyed.try_join_next().is_some() {}
+
+            self.unkeyed.spawn(async move {
+                if let Ok(_permit) = semaphore.acquire().await {
+                    dispatcher.handle_update(account, update).await;
+                }
+            });
+
+            return;
+        };
+
+        let mut item = (account, update);
+
+        loop {
+            let queue = self
+                .queues
+                .entry(key.clone())
+                .or_insert_with(|| ChatQueue::spawn(dispatcher, semaphore, self.queue_size));
+
+            queue.pending.fetch_add(1, Ordering::AcqRel);
+            metrics.queued.fetch_add(1, Ordering::Relaxed);
+
+            let closed = match queue.sender.try_send(item) {
+                Ok(()) => None,
+                Err(TrySendError::Full(full)) => match self.overflow {
+                    QueueOverflow::Wait => {
+                        metrics.backpressure_waits.fetch_add(1, Ordering::Relaxed);
+                        queue.sender.send(full).await.err().map(|e| e.0)
+                    }
+                    QueueOverflow::Drop => {
+                        log::warn!(
+                            "Chat queue {} of {} is full, dropping the update",
+                            key.1,
+                            key.0
+                        );
+
+                        queue.pending.fetch_sub(1, Ordering::AcqRel);
+                        metrics.queued.fetch_sub(1, Ordering::Relaxed);
+                        metrics.dropped_updates.fetch_add(1, Ordering::Relaxed);
+                        None
+                    }
+                },
+                Err(TrySendError::Closed(closed)) => Some(closed),
+            };
+
+            // The queue task is gone (a handler panicked), start a new one.
+            match closed {
+                Some(closed) => {
+                    log::error!(
+                        "Chat queue {} of {} closed unexpectedly, restarting it",
+                        key.1,
+                        key.0
+                    );
+
+                    metrics.queued.fetch_sub(1, Ordering::Relaxed);
+                    self.queues.remove(&key);
+                    item = closed;
+                }
+                None => break,
+            }
+        }
+
+        metrics
+            .active_chats
+            .store(self.queues.len(), Ordering::Relaxed);
+    }
+
+    /// Close all the queues and wait for their pending updates, and the ones without chat.
+    async fn close(mut self, metrics: &Metrics) {
+        for (_, queue) in self.queues {
+            drop(queue.sender);
+            let _ = queue.worker.await;
+        }
+
+        while self.unkeyed.join_next().await.is_some() {}
+
+        metrics.active_chats.store(0, Ordering::Relaxed);
+    }
+}
+
+impl ChatQueue {
+    /// Spawn the task which handles the chat updates in order.
+    fn spawn(dispatcher: &Arc<Dispatcher>, semaphore: &Arc<Semaphore>, size: usize) -> Self {
+        let (sender, mut receiver) = mpsc::channel::<(Account, Update)>(size);
+        let pending = Arc::new(AtomicUsize::new(0));
+
+        let dispatcher = Arc::clone(dispatcher);
+        let semaphore = Arc::clone(semaphore);
+        let worker_pending = Arc::clone(&pending);
+
+        let worker = tokio::spawn(async move {
+            while let Some((account, update)) = receiver.recv().await {
+                if let Ok(_permit) = semaphore.acquire().await {
+                    dispatcher.handle_update(account, update).await;
+                }
+
+                worker_pending.fetch_sub(1, Ordering::AcqRel);
+                dispatcher.metrics.queued.fetch_sub(1, Ordering::Relaxed);
+            }
+        });
+
+        Self {
+            sender,
+            pending,
+            worker,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::{sources::ChannelUpdateSource, traits::SourceResult};
+
+    /// A source which always fails.
+    struct FailingSource;
+
+    #[async_trait]
+    impl UpdateSource for FailingSource {
+        fn accounts(&self) -> Vec<Account> {
+            Vec::new()
+        }
+
+        async fn next_update(&mut self) -> SourceResult {
+            Err(Error::other("disconnected"))
+        }
+    }
+
+    #[test]
+    fn shutdown_while_handler_is_blocked() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let dispatcher = Dispatcher::default();
+            let shutdown = dispatcher.shutdown_handle();
+
+            // Held by the blocked handler, the limit is reached.
+            let semaphore = Arc::new(Semaphore::new(1));
+            let _permit = Arc::clone(&semaphore).acquire_owned().await.unwrap();
+
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                shutdown.shutdown();
+            });
+
+            let result =
+                tokio::time::timeout(Duration::from_secs(1), dispatcher.acquire(&semaphore)).await;
+
+            assert!(matches!(result, Ok(Err(StopReason::Shutdown))));
+        });
+    }
+
+    #[test]
+    fn fan_in_source_gives_up() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let (sender, receiver) = mpsc::channel(1);
+            let calls = Arc::new(Mutex::new(Vec::new()));
+
+            let source = sources::fan_in(vec![
+                Box::new(ChannelUpdateSource::new(Vec::new(), receiver)),
+                Box::new(FailingSource),
+            ])
+            .retry_policy(
+                RetryPolicy::new()
+                    .max_retries(3)
+                    .initial_backoff(Duration::from_millis(1)),
+            )
+            .on_error({
+                let calls = Arc::clone(&calls);
+                move |_, failures| calls.lock().unwrap().push(failures)
+            });
+
+            // The other source keeps running after the failing one gives up.
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                drop(sender);
+            });
+
+            // Retrying it again would wait for the dispatcher's backoff, of 1 second.
+            let dispatcher = Dispatcher::default().handle_ctrl_c(false);
+            let result =
+                tokio::time::timeout(Duration::from_millis(500), dispatcher.run_with(source)).await;
+
+            assert!(matches!(result, Ok(Err(DispatcherError::UpdateSource(_)))));
+            assert_eq!(*calls.lock().unwrap(), vec![1, 2, 3, 4]);
+        });
     }
 }
@@ -0,0 +1,150 @@
+// Copyright (C) 2024 AndrielFR
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::{error::Error as StdError, fmt, ops::Deref};
+
+use grammers_client::InvocationError;
+
+/// A `Result` with [`Error`] as default error.
+///
+/// [`Error`]: crate::Error
+pub type Result<T = (), E = Error> = std::result::Result<T, E>;
+
+/// Error.
+///
+/// The crate-wide error, `Send + Sync` so handlers can run on any runtime.
+///
+/// Any error or message can be converted into it with `?`, like `anyhow`,
+/// Which is why it doesn't implement [`std::error::Error`] itself,
+/// But derefs to it.
+///
+/// ```ignore
+/// return Err("missing user")?;
+/// ```
+#[derive(Debug)]
+pub enum Error {
+    /// An invocation made to Telegram failed.
+    Invocation(InvocationError),
+
+    /// A filter failed.
+    Filter(Box<dyn StdError + Send + Sync>),
+
+    /// A handler failed.
+    ///
+    /// Wraps the errors returned by the handlers, other than the invocation ones.
+    Handler(Box<dyn StdError + Send + Sync>),
+
+    /// Any other error, raised by user code outside the handlers.
+    Other(Box<dyn StdError + Send + Sync>),
+}
+
+impl Error {
+    /// Construct a new filter error.
+    ///
+    /// Receives any error or message.
+    pub fn filter(error: impl Into<Box<dyn StdError + Send + Sync>>) -> Self {
+        Self::Filter(error.into())
+    }
+
+    /// Construct a new handler error.
+    ///
+    /// Receives any error or message.
+    pub fn handler(error: impl Into<Box<dyn StdError + Send + Sync>>) -> Self {
+        Self::Handler(error.into())
+    }
+
+    /// Construct a new error.
+    ///
+    /// Receives any error or message.
+    pub fn other(error: impl Into<Box<dyn StdError + Send + Sync>>) -> Self {
+        Self::Other(error.into())
+    }
+
+    /// Checks if the inner error is of type `E`.
+    pub fn is<E: StdError + 'static>(&self) -> bool {
+        self.downcast_ref::<E>().is_some()
+    }
+
+    /// Get the inner error as `E`.
+    pub fn downcast_ref<E: StdError + 'static>(&self) -> Option<&E> {
+        (**self).downcast_ref::<E>()
+    }
+
+    /// Get the inner error.
+    pub fn into_inner(self) -> Box<dyn StdError + Send + Sync> {
+        match self {
+            Self::Invocation(e) => Box::new(e),
+            Self::Filter(e) | Self::Handler(e) | Self::Other(e) => e,
+        }
+    }
+}
+
+impl<E> From<E> for Error
+where
+    E: Into<Box<dyn StdError + Send + Sync>>,
+{
+    fn from(error: E) -> Self {
+        let error: Box<dyn StdError + Send + Sync> = error.into();
+
+        match error.downcast::<InvocationError>() {
+            Ok(e) => Self::Invocation(*e),
+            Err(e) => Self::Other(e),
+        }
+    }
+}
+
+impl Deref for Error {
+    type Target = dyn StdError + Send + Sync + 'static;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Invocation(e) => e,
+            Self::Filter(e) | Self::Handler(e) | Self::Other(e) => e.as_ref(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invocation(e) => write!(f, "invocation failed: {}", e),
+            Self::Filter(e) => write!(f, "filter failed: {}", e),
+            Self::Handler(e) => write!(f, "handler failed: {}", e),
+            Self::Other(e) => e.fmt(f),
+        }
+    }
+}
+
+/// Dispatcher Error.
+///
+/// Why the dispatcher failed.
+#[derive(Debug)]
+pub enum DispatcherError {
+    /// A module's `on_startup` failed, no update was handled.
+    Startup(Error),
+
+    /// The update source kept failing after all the retries.
+    UpdateSource(Error),
+}
+
+impl fmt::Display for DispatcherError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Startup(e) => write!(f, "startup failed: {}", e),
+            Self::UpdateSource(e) => write!(f, "update source failed: {}", e),
+        }
+    }
+}
+
+impl StdError for DispatcherError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::Startup(e) | Self::UpdateSource(e) => Some(&**e),
+        }
+    }
+}
@@ -0,0 +1,195 @@
+// Copyright (C) 2024 AndrielFR
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Extractors.
+//!
+//! The handler arguments which implement [`FromUpdate`], besides these ones:
+//! [`Client`], [`Update`], [`Message`], [`CallbackQuery`], [`Chat`], [`Account`]
+//! And [`Data`].
+//!
+//! Any of them can be wrapped in an `Option` to not skip the handler when missing.
+//!
+//! The arguments parsed by the [`query`] filter are taken with [`QueryArgs`].
+//!
+//! [`FromUpdate`]: crate::traits::FromUpdate
+//! [`Account`]: crate::Account
+//! [`Data`]: crate::Data
+//! [`query`]: crate::filters::query
+//! [`QueryArgs`]: crate::filters::QueryArgs
+
+use std::{ops::Deref, sync::Arc};
+
+use grammers_client::{
+    types::{CallbackQuery, Chat, Message},
+    Client, Update,
+};
+
+use crate::{
+    traits::{FromCommandArgs, FromUpdate},
+    utils, Account, CommandArgs, Context, Data, Error, Result,
+};
+
+/// The sender of the update.
+#[derive(Clone)]
+pub struct Sender(pub Chat);
+
+/// A module of the [`Data`].
+///
+/// [`Data`]: crate::Data
+pub struct Module<T>(pub Arc<T>);
+
+/// A value left in the [`Context`] by the filters or middlewares, like the parsed arguments.
+///
+/// Unlike the other extractors, only taken after the before-type middlewares.
+///
+/// [`Context`]: crate::Context
+#[derive(Clone)]
+pub struct Args<T>(pub T);
+
+/// The arguments of the matched command, parsed by [`FromCommandArgs`].
+///
+/// If they couldn't be parsed, the usage error is replied instead of running the handler,
+/// Only parsed after the before-type middlewares, as [`Args`].
+///
+/// [`FromCommandArgs`]: crate::traits::FromCommandArgs
+#[derive(Clone)]
+pub struct Command<T>(pub T);
+
+impl<T> Deref for Command<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Deref for Sender {
+    type Target = Chat;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> Deref for Module<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> Deref for Args<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl FromUpdate for Client {
+    fn from_update(client: &Client, _: &Update, _: &Data, _: &Context) -> Result<Option<Self>> {
+        Ok(Some(client.clone()))
+    }
+}
+
+impl FromUpdate for Update {
+    fn from_update(_: &Client, update: &Update, _: &Data, _: &Context) -> Result<Option<Self>> {
+        Ok(Some(update.clone()))
+    }
+}
+
+impl FromUpdate for Message {
+    fn from_update(_: &Client, update: &Update, _: &Data, _: &Context) -> Result<Option<Self>> {
+        Ok(utils::get_message(update))
+    }
+}
+
+impl FromUpdate for CallbackQuery {
+    fn from_update(_: &Client, update: &Update, _: &Data, _: &Context) -> Result<Option<Self>> {
+        Ok(utils::get_query(update))
+    }
+}
+
+impl FromUpdate for Chat {
+    fn from_update(_: &Client, update: &Update, _: &Data, _: &Context) -> Result<Option<Self>> {
+        Ok(utils::get_chat(update))
+    }
+}
+
+impl FromUpdate for Sender {
+    fn from_update(_: &Client, update: &Update, _: &Data, _: &Context) -> Result<Option<Self>> {
+        Ok(utils::get_sender(update).map(Sender))
+    }
+}
+
+impl FromUpdate for Account {
+    fn from_update(_: &Client, _: &Update, data: &Data, _: &Context) -> Result<Option<Self>> {
+        Ok(data
+            .get::<Account>()
+            .map(|account| Account::clone(&account)))
+    }
+}
+
+impl FromUpdate for Data {
+    fn from_update(_: &Client, _: &Update, data: &Data, _: &Context) -> Result<Option<Self>> {
+        Ok(Some(data.clone()))
+    }
+}
+
+impl<T: crate::traits::Module> FromUpdate for Module<T> {
+    fn from_update(_: &Client, _: &Update, data: &Data, _: &Context) -> Result<Option<Self>> {
+        Ok(data.get::<T>().map(Module))
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> FromUpdate for Args<T> {
+    fn from_update(_: &Client, _: &Update, _: &Data, context: &Context) -> Result<Option<Self>> {
+        Ok(context.get::<T>().cloned().map(Args))
+    }
+
+    /// Always pass, as the middlewares may insert them.
+    fn check(_: &Client, _: &Update, _: &Data, _: &Context) -> Result<bool> {
+        Ok(true)
+    }
+}
+
+impl<T: FromCommandArgs> FromUpdate for Command<T> {
+    fn from_update(_: &Client, _: &Update, _: &Data, context: &Context) -> Result<Option<Self>> {
+        match context.get::<CommandArgs>() {
+            Some(args) => T::from_command_args(args)
+                .map(|args| Some(Command(args)))
+                .map_err(Error::filter),
+            None => Ok(None),
+        }
+    }
+
+    /// Only checks if a command matched, the arguments are parsed after the middlewares.
+    fn check(_: &Client, _: &Update, _: &Data, context: &Context) -> Result<bool> {
+        Ok(context.contains::<CommandArgs>())
+    }
+
+    fn usage() -> Option<String> {
+        Some(T::usage()).filter(|usage| !usage.is_empty())
+    }
+}
+
+impl<T: FromUpdate> FromUpdate for Option<T> {
+    fn from_update(
+        client: &Client,
+        update: &Update,
+        data: &Data,
+        context: &Context,
+    ) -> Result<Option<Self>> {
+        T::from_update(client, update, data, context).map(Some)
+    }
+
+    fn usage() -> Option<String> {
+        T::usage()
+    }
+}
@@ -5,3 +5,43 @@
 // <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
+
+use grammers_client::{Client, Update};
+
+use crate::{traits::Filter, Context};
+
+/// Checks if `filter` passes.
+///
+/// If not, the `context` is restored, undoing what the filter changed.
+pub(crate) async fn check(
+    filter: &dyn Filter,
+    client: &Client,
+    update: &Update,
+    context: &mut Context,
+) -> bool {
+    check_all(&[filter], client, update, context)
+        .await
+        .is_some()
+}
+
+/// Checks if all the `filters` pass, in order.
+///
+/// If not, the `context` is restored, undoing what the filters changed.
+/// Else, returns the `context` from before them, to undo them later.
+pub(crate) async fn check_all(
+    filters: &[&dyn Filter],
+    client: &Client,
+    update: &Update,
+    context: &mut Context,
+) -> Option<Context> {
+    let snapshot = context.clone();
+
+    for filter in filters.iter() {
+        if !filter.is_ok(client, update, context).await {
+            *context = snapshot;
+            return None;
+        }
+    }
+
+    Some(snapshot)
+}
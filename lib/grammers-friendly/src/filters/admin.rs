@@ -6,27 +6,31 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::collections::{hash_map::Entry, HashMap};
+use std::{collections::HashMap, sync::Arc};
 
 use async_trait::async_trait;
 use grammers_client::{client::chats::ParticipantPermissions, Client, Update};
+use tokio::sync::Mutex;
 
-use crate::traits::{Filter, GetChat, GetSender};
+use crate::{
+    traits::{Filter, GetChat, GetSender},
+    Context,
+};
 
 /// Admin filter.
 ///
 /// Checks for user perms in chat.
 /// Pass if user has admin rights.
 ///
-/// Has internal cache.
+/// Has internal cache, shared between its clones.
 #[derive(Clone, Default)]
 pub struct AdminFilter {
-    perms: HashMap<i64, HashMap<i64, ParticipantPermissions>>,
+    perms: Arc<Mutex<HashMap<i64, HashMap<i64, ParticipantPermissions>>>>,
 }
 
 #[async_trait]
 impl Filter for AdminFilter {
-    async fn is_ok(&mut self, client: &Client, update: &Update) -> bool {
+    async fn is_ok(&self, client: &Client, update: &Update, _context: &mut Context) -> bool {
         let chat = update.get_chat();
         let user = update.get_sender();
 
@@ -36,24 +40,28 @@ impl Filter for AdminFilter {
             if let Some(user) = user {
                 let user_id = user.id();
 
-                if let Entry::Vacant(e) = self.perms.entry(chat_id) {
-                    let perms = client.get_permissions(&chat, &user).await;
-                    if let Ok(perms) = perms {
-                        let mut hash = HashMap::new();
-                        hash.insert(user_id, perms.clone());
-                        e.insert(hash);
-                        return perms.is_admin() || perms.is_creator();
-                    }
-                } else if let Some(hash) = self.perms.get_mut(&chat_id) {
-                    if hash.get(&user_id).is_none() {
-                        let perms = client.get_permissions(&chat, &user).await;
-                        if let Ok(perms) = perms {
-                            hash.insert(user_id, perms.clone());
-                            return perms.is_admin() || perms.is_creator();
-                        }
-                    } else if let Some(perms) = hash.get(&user_id) {
-                        return perms.is_admin() || perms.is_creator();
-                    }
+                if let Some(perms) = self
+                    .perms
+                    .lock()
+                    .await
+                    .get(&chat_id)
+                    .and_then(|hash| hash.get(&user_id))
+                {
+                    return perms.is_admin() || perms.is_creator();
+                }
+
+                let perms = client.get_permissions(&chat, &user).await;
+                if let Ok(perms) = perms {
+                    let is_admin = perms.is_admin() || perms.is_creator();
+
+                    self.perms
+                        .lock()
+                        .await
+                        .entry(chat_id)
+                        .or_default()
+                        .insert(user_id, perms);
+
+                    return is_admin;
                 }
             }
         }
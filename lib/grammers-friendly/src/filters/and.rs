@@ -9,7 +9,7 @@
 use async_trait::async_trait;
 use grammers_client::{Client, Update};
 
-use crate::traits::Filter;
+use crate::{filter, traits::Filter, CommandInfo, Context};
 
 /// And filter.
 ///
@@ -31,8 +31,17 @@ impl AndFilter {
 
 #[async_trait]
 impl Filter for AndFilter {
-    async fn is_ok(&mut self, client: &Client, update: &Update) -> bool {
-        self.first.is_ok(client, update).await && self.second.is_ok(client, update).await
+    async fn is_ok(&self, client: &Client, update: &Update, context: &mut Context) -> bool {
+        filter::check_all(&[&*self.first, &*self.second], client, update, context)
+            .await
+            .is_some()
+    }
+
+    fn commands(&self) -> Vec<CommandInfo> {
+        let mut commands = self.first.commands();
+        commands.extend(self.second.commands());
+
+        commands
     }
 }
 
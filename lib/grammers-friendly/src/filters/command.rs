@@ -6,85 +6,218 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use std::{collections::HashMap, sync::Arc};
+
 use async_trait::async_trait;
 use grammers_client::{Client, Update};
-use regex::Regex;
+use tokio::sync::Mutex;
 
-use crate::traits::{Filter, GetMessage};
+use crate::{
+    command::Matcher,
+    traits::{Filter, GetMessage},
+    Account, CommandInfo, CommandScope, Context, Error, Result, DEFAULT_ACCOUNT,
+};
 
 /// Command filter.
 ///
-/// Pass if `command` match.
+/// Pass if `command`, or one of its aliases, match.
+///
+/// The parsed [`CommandArgs`] are left in the [`Context`].
+///
+/// The command is matched literally, it isn't a regex anymore: `start|help` is still taken
+/// As the `start` command with the `help` alias, but the arguments can't be part of it,
+/// They are parsed into the [`CommandArgs`] instead.
+///
+/// `/command@username` only pass if `username` is of the client which received it,
+/// Cached per account and shared between its clones.
+///
+/// [`CommandArgs`]: crate::CommandArgs
+/// [`Context`]: crate::Context
 #[derive(Clone)]
 pub struct CommandFilter {
-    is_bot: bool,
-
-    prefixes: String,
-    command: String,
-
-    username: Option<String>,
+    matcher: Matcher,
+    info: CommandInfo,
+    usernames: Arc<Mutex<HashMap<String, Option<String>>>>,
 }
 
 impl CommandFilter {
+    /// Construct a new command filter.
+    ///
+    /// # Panics
+    ///
+    /// If the command is invalid, see [`CommandFilter::try_new`].
     pub fn new(prefixes: impl Into<String>, command: impl Into<String>) -> Self {
-        Self {
-            is_bot: true,
-
-            prefixes: prefixes.into(),
-            command: command.into(),
+        Self::try_new(prefixes, command).unwrap_or_else(|e| panic!("{}", e))
+    }
 
-            username: None,
+    /// Construct a new command filter.
+    ///
+    /// Receives the `prefixes`, each char being one, and the `command`,
+    /// Whose aliases can follow it separated by `|`, as `start|help`.
+    ///
+    /// Fails if the command or an alias is empty or has whitespaces.
+    pub fn try_new(prefixes: impl Into<String>, command: impl Into<String>) -> Result<Self> {
+        let prefixes = prefixes.into();
+        let command = command.into();
+
+        if let Some(name) = command
+            .split('|')
+            .find(|name| name.is_empty() || name.contains(char::is_whitespace))
+        {
+            return Err(Error::other(format!(
+                "invalid name `{}` of the command `{}`",
+                name, command
+            )));
         }
+
+        let mut names = command.split('|');
+        let name = names.next().unwrap_or_default().to_string();
+
+        let filter = Self {
+            matcher: Matcher::new(&prefixes, name.clone()),
+            info: CommandInfo::new(&prefixes, name),
+            usernames: Arc::new(Mutex::new(HashMap::new())),
+        };
+
+        Ok(names.fold(filter, |filter, alias| filter.alias(alias)))
     }
-}
 
-#[async_trait]
-impl Filter for CommandFilter {
-    async fn is_ok(&mut self, client: &Client, update: &Update) -> bool {
-        let message = update.get_message();
+    /// Attach a new alias to the command.
+    ///
+    /// # Panics
+    ///
+    /// If the alias is empty or has whitespaces.
+    pub fn alias(mut self, alias: impl Into<String>) -> Self {
+        let alias = alias.into();
+        assert!(
+            !alias.is_empty() && !alias.contains(char::is_whitespace),
+            "invalid alias `{}` of the command `{}`",
+            alias,
+            self.info.name()
+        );
+
+        self.matcher.push_alias(alias.clone());
+        self.info.push_alias(alias);
+        self
+    }
 
-        let mut command = self.command.clone();
+    /// Set the command description, shown in the help.
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.info.set_description(description.into());
+        self
+    }
 
-        if let Some(message) = message {
-            let text = message.text();
+    /// Set the command description in `language`, as `en`, shown in the bot command menu.
+    pub fn description_in(
+        mut self,
+        language: impl Into<String>,
+        description: impl Into<String>,
+    ) -> Self {
+        self.info
+            .push_description(language.into(), description.into());
+        self
+    }
 
-            if self.prefixes.is_empty() {
-                let input = text.split_whitespace().next().unwrap().to_string();
-                return input == command;
-            }
+    /// Attach a new scope of the command in the bot command menu.
+    ///
+    /// By default, the command is only in the [`CommandScope::Default`] one.
+    ///
+    /// [`CommandScope::Default`]: crate::CommandScope::Default
+    pub fn scope(mut self, scope: CommandScope) -> Self {
+        self.info.push_scope(scope);
+        self
+    }
 
-            if self.username.is_none() && self.is_bot {
-                let me = client.get_me().await;
+    /// Set the arguments usage, shown in the help.
+    ///
+    /// As `<user> [duration] <reason...>`, see [`FromCommandArgs::usage`].
+    ///
+    /// [`FromCommandArgs::usage`]: crate::traits::FromCommandArgs::usage
+    pub fn usage(mut self, usage: impl Into<String>) -> Self {
+        self.info.set_usage(usage.into());
+        self
+    }
+
+    /// Set the command category, which groups the commands in the help.
+    pub fn category(mut self, category: impl Into<String>) -> Self {
+        self.info.set_category(category.into());
+        self
+    }
 
-                if let Ok(me) = me {
-                    if me.is_bot() {
-                        self.username = me.username().map(String::from);
-                    } else {
-                        self.is_bot = false;
-                    }
+    /// Set if the command is hidden from the help and the bot command menu.
+    ///
+    /// By default, is `false`.
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.info.set_hidden(hidden);
+        self
+    }
+
+    /// Set if the command case is ignored.
+    ///
+    /// By default, is `false`.
+    pub fn ignore_case(mut self, ignore_case: bool) -> Self {
+        self.matcher.set_ignore_case(ignore_case);
+        self
+    }
+
+    /// Checks if the `mention` is the username of the `account` client.
+    ///
+    /// The username is get on the first mention of each account.
+    async fn is_mentioned(&self, client: &Client, account: &str, mention: &str) -> bool {
+        let cached = self.usernames.lock().await.get(account).cloned();
+
+        let username = match cached {
+            Some(username) => username,
+            None => match client.get_me().await {
+                Ok(me) => {
+                    let username = me.username().map(String::from);
+
+                    self.usernames
+                        .lock()
+                        .await
+                        .insert(account.to_string(), username.clone());
+
+                    username
                 }
-            } else if let Some(username) = self.username.clone() {
-                // Username is mandatory to bots
-                command = command
-                    .split_whitespace()
-                    .enumerate()
-                    .map(|(pos, word)| {
-                        if pos == 0 {
-                            format!(r#"{0}(@{1})?"#, word, username)
-                        } else {
-                            word.to_string()
-                        }
-                    })
-                    .collect::<String>();
-            }
+                Err(e) => {
+                    log::error!("Error while getting the client username: {:?}", e);
+                    return false;
+                }
+            },
+        };
 
-            let regex =
-                Regex::new(format!(r#"^[{0}]({1}$|{1}(\s))"#, self.prefixes, command).as_str())
-                    .unwrap();
-            return regex.is_match(text);
+        username.is_some_and(|username| username.eq_ignore_ascii_case(mention))
+    }
+}
+
+#[async_trait]
+impl Filter for CommandFilter {
+    async fn is_ok(&self, client: &Client, update: &Update, context: &mut Context) -> bool {
+        let Some(message) = update.get_message() else {
+            return false;
+        };
+
+        let Some(args) = self.matcher.parse(message.text()) else {
+            return false;
+        };
+
+        if let Some(mention) = args.mention() {
+            let account = context
+                .get::<Account>()
+                .map_or(DEFAULT_ACCOUNT, Account::name);
+
+            if !self.is_mentioned(client, account, mention).await {
+                return false;
+            }
         }
 
-        false
+        context.insert(args);
+
+        true
+    }
+
+    fn commands(&self) -> Vec<CommandInfo> {
+        vec![self.info.clone()]
     }
 }
 
@@ -12,7 +12,7 @@ use async_trait::async_trait;
 use futures_util::Future;
 use grammers_client::{Client, Update};
 
-use crate::traits::Filter;
+use crate::{traits::Filter, Context};
 
 type PinBox = Pin<Box<dyn Future<Output = bool> + Send>>;
 
@@ -56,7 +56,7 @@ impl CustomFilter {
 
 #[async_trait]
 impl Filter for CustomFilter {
-    async fn is_ok(&mut self, client: &Client, update: &Update) -> bool {
+    async fn is_ok(&self, client: &Client, update: &Update, _context: &mut Context) -> bool {
         self.func.call(client.clone(), update.clone()).await
     }
 }
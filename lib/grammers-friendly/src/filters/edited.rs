@@ -9,7 +9,7 @@
 use async_trait::async_trait;
 use grammers_client::{Client, Update};
 
-use crate::traits::Filter;
+use crate::{traits::Filter, Context};
 
 /// Edited filter.
 ///
@@ -19,7 +19,7 @@ pub struct EditedFilter;
 
 #[async_trait]
 impl Filter for EditedFilter {
-    async fn is_ok(&mut self, _client: &Client, update: &Update) -> bool {
+    async fn is_ok(&self, _client: &Client, update: &Update, _context: &mut Context) -> bool {
         matches!(update, Update::MessageEdited(_))
     }
 }
@@ -9,7 +9,7 @@
 use async_trait::async_trait;
 use grammers_client::{Client, Update};
 
-use crate::traits::Filter;
+use crate::{traits::Filter, Context};
 
 /// Not filter.
 ///
@@ -31,8 +31,14 @@ impl NotFilter {
 
 #[async_trait]
 impl Filter for NotFilter {
-    async fn is_ok(&mut self, client: &Client, update: &Update) -> bool {
-        !self.filter.is_ok(client, update).await
+    async fn is_ok(&self, client: &Client, update: &Update, context: &mut Context) -> bool {
+        // Restored even if it passes, what it changed doesn't matter once negated.
+        let snapshot = context.clone();
+
+        let passed = self.filter.is_ok(client, update, context).await;
+        *context = snapshot;
+
+        !passed
     }
 }
 
@@ -9,7 +9,7 @@
 use async_trait::async_trait;
 use grammers_client::{Client, Update};
 
-use crate::traits::Filter;
+use crate::{filter, traits::Filter, CommandInfo, Context};
 
 /// Or filter.
 ///
@@ -31,8 +31,16 @@ impl OrFilter {
 
 #[async_trait]
 impl Filter for OrFilter {
-    async fn is_ok(&mut self, client: &Client, update: &Update) -> bool {
-        self.first.is_ok(client, update).await || self.other.is_ok(client, update).await
+    async fn is_ok(&self, client: &Client, update: &Update, context: &mut Context) -> bool {
+        filter::check(&*self.first, client, update, context).await
+            || filter::check(&*self.other, client, update, context).await
+    }
+
+    fn commands(&self) -> Vec<CommandInfo> {
+        let mut commands = self.first.commands();
+        commands.extend(self.other.commands());
+
+        commands
     }
 }
 
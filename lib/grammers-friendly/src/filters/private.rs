@@ -9,7 +9,10 @@
 use async_trait::async_trait;
 use grammers_client::{types::Chat, Client, Update};
 
-use crate::traits::{Filter, GetChat};
+use crate::{
+    traits::{Filter, GetChat},
+    Context,
+};
 
 /// Private filter.
 ///
@@ -19,7 +22,7 @@ pub struct PrivateFilter;
 
 #[async_trait]
 impl Filter for PrivateFilter {
-    async fn is_ok(&mut self, _client: &Client, update: &Update) -> bool {
+    async fn is_ok(&self, _client: &Client, update: &Update, _context: &mut Context) -> bool {
         let chat = update.get_chat();
 
         if let Some(chat) = chat {
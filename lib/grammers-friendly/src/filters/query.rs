@@ -6,88 +6,190 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use std::{collections::HashMap, error::Error as StdError, fmt};
+
 use async_trait::async_trait;
 use grammers_client::{Client, Update};
 use regex::Regex;
 
-use crate::traits::{Filter, GetMessage, GetQuery};
+use crate::{
+    traits::{Filter, FromQueryValue, FromUpdate, GetMessage, GetQuery},
+    Context, Data, Error, Result,
+};
 
 /// Query filter.
 ///
 /// Pass if `query` match.
 ///
-/// It's just a beautiful regex.
+/// It's just a beautiful regex, whose variables, `name:type`, are parsed to [`QueryArgs`].
+///
+/// The types are `int`, `float` (or `double`), `bool`, `str`, `sym` and `all`,
+/// Optional if followed by `:maybe` or `?`.
+///
+/// [`QueryArgs`]: crate::filters::QueryArgs
 #[derive(Clone)]
 pub struct QueryFilter {
     query: Regex,
+    vars: Vec<(String, VarType)>,
+}
+
+/// The type of a query variable.
+#[derive(Clone, Copy)]
+enum VarType {
+    All,
+    Int,
+    Str,
+    Sym,
+    Bool,
+    Float,
+}
+
+impl VarType {
+    /// Get the pattern of the type.
+    ///
+    /// The converted types only match their values.
+    fn pattern(self) -> &'static str {
+        match self {
+            Self::All => r"\S+",
+            Self::Int => r"-?\d+\b",
+            Self::Str => r"\w+",
+            Self::Sym => r"\W+",
+            Self::Bool => r"(?i:true|false|yes|no|1|0)\b",
+            Self::Float => r"-?\d+(?:\.\d+)?\b",
+        }
+    }
+
+    /// Convert the `value` to the type.
+    fn convert(self, value: &str) -> Option<QueryValue> {
+        match self {
+            Self::All | Self::Str | Self::Sym => Some(QueryValue::Str(value.to_string())),
+            Self::Int => value.parse().ok().map(QueryValue::Int),
+            Self::Float => value.parse().ok().map(QueryValue::Float),
+            Self::Bool => match value.to_lowercase().as_str() {
+                "true" | "yes" | "1" => Some(QueryValue::Bool(true)),
+                "false" | "no" | "0" => Some(QueryValue::Bool(false)),
+                _ => None,
+            },
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::All => "all",
+            Self::Int => "int",
+            Self::Str => "str",
+            Self::Sym => "sym",
+            Self::Bool => "bool",
+            Self::Float => "float",
+        }
+    }
 }
 
 impl QueryFilter {
+    /// Construct a new query filter.
+    ///
+    /// # Panics
+    ///
+    /// If the query is invalid, see [`QueryFilter::try_new`].
     pub fn new(query: impl Into<String>) -> Self {
+        Self::try_new(query).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Construct a new query filter.
+    ///
+    /// Fails if a variable type is unknown, a variable name is invalid or repeated,
+    /// Or the query is an invalid regex.
+    pub fn try_new(query: impl Into<String>) -> Result<Self> {
         let query = query.into();
-        let mut new_query = Vec::new();
-
-        query
-            .split_whitespace()
-            .enumerate()
-            .for_each(|(pos, word)| {
-                if pos == 0 || word.contains('(') {
-                    new_query.push(word.to_string());
-                    return;
+        let mut vars = Vec::new();
+        let mut new_query = String::from("^");
+
+        for (pos, word) in query.split_whitespace().enumerate() {
+            if pos == 0 || word.contains('(') || !word.contains(':') {
+                if pos > 0 {
+                    new_query.push_str(r"\s+");
                 }
+                new_query.push_str(word);
+                continue;
+            }
+
+            let mut maybe = word.contains('?');
+
+            let mut splitted = word.trim().split(':');
+            let var = splitted.next().unwrap().trim_end_matches('?');
+            let ty = splitted.next().unwrap().trim_end_matches('?');
 
-                if word.contains(':') {
-                    let mut maybe = false;
-
-                    let (_var, mut ty) = word.trim().split_once(':').unwrap();
-                    if ty.contains(':') {
-                        let mut splitted = ty.split(':');
-                        ty = splitted.next().unwrap();
-
-                        splitted.for_each(|word| match word {
-                            "my" | "may" | "maybe" => {
-                                maybe = true;
-                            }
-                            _ => {}
-                        });
-                    }
-
-                    let mut new_word = match ty {
-                        "all" => r"[\w+|\d+|\d+\.\d+|true|false|yes|no|1|0]",
-                        "int" => r"\d+",
-                        "str" => r"\w+",
-                        "sym" => r"\W+",
-                        "bool" => "[true|false|yes|no|1|0]",
-                        "float" | "double" => r"\d+\.\d+",
-                        _ => "",
-                    }
-                    .to_string();
-                    if !new_word.contains('[') {
-                        new_word = format!("({})", new_word);
-                    }
-
-                    if word.contains('?') || maybe {
-                        new_word.push('?');
-                    }
-
-                    new_query.push(new_word);
-                } else {
-                    new_query.push(word.to_string());
+            splitted.for_each(|word| match word {
+                "my" | "may" | "maybe" => {
+                    maybe = true;
                 }
+                _ => {}
             });
 
-        let mut query = new_query.join(r"\s");
-        query.insert(0, '^');
+            let ty = match ty {
+                "all" => VarType::All,
+                "int" => VarType::Int,
+                "str" => VarType::Str,
+                "sym" => VarType::Sym,
+                "bool" => VarType::Bool,
+                "float" | "double" => VarType::Float,
+                _ => {
+                    return Err(Error::other(format!(
+                        "unknown type `{}` of the query variable `{}`",
+                        ty, var
+                    )))
+                }
+            };
+
+            let group = format!(r"\s+(?P<{}>{})", var, ty.pattern());
+            if maybe {
+                new_query.push_str(&format!("(?:{})?", group));
+            } else {
+                new_query.push_str(&group);
+            }
 
-        Self {
-            query: Regex::new(&query).unwrap(),
+            vars.push((var.to_string(), ty));
         }
+
+        let query = Regex::new(&new_query)
+            .map_err(|e| Error::other(format!("invalid query `{}`: {}", query, e)))?;
+
+        Ok(Self { query, vars })
+    }
+
+    /// Parse the variables of the `text`, once the `query` matched.
+    ///
+    /// Returns `None` if `query` doesn't match.
+    fn parse(&self, text: &str) -> Option<std::result::Result<QueryArgs, QueryError>> {
+        let captures = self.query.captures(text)?;
+        let mut values = HashMap::new();
+
+        for (var, ty) in self.vars.iter() {
+            let Some(value) = captures.name(var) else {
+                continue;
+            };
+
+            match ty.convert(value.as_str()) {
+                Some(converted) => {
+                    values.insert(var.clone(), converted);
+                }
+                None => {
+                    return Some(Err(QueryError {
+                        var: var.clone(),
+                        ty: ty.name(),
+                        value: value.as_str().to_string(),
+                    }))
+                }
+            }
+        }
+
+        Some(Ok(QueryArgs { values }))
     }
 }
 
 #[async_trait]
 impl Filter for QueryFilter {
-    async fn is_ok(&mut self, _client: &Client, update: &Update) -> bool {
+    async fn is_ok(&self, _client: &Client, update: &Update, context: &mut Context) -> bool {
         let message = update.get_message();
         let query = update.get_query();
 
@@ -96,10 +198,22 @@ impl Filter for QueryFilter {
         if let Some(message) = message {
             text = message.text().to_string();
         } else if let Some(query) = query {
-            text = String::from_utf8(query.data().into()).unwrap();
+            text = String::from_utf8_lossy(query.data()).into_owned();
+        }
+
+        match self.parse(&text) {
+            Some(Ok(args)) => {
+                context.remove::<QueryError>();
+                context.insert(args);
+            }
+            Some(Err(e)) => {
+                context.remove::<QueryArgs>();
+                context.insert(e);
+            }
+            None => return false,
         }
 
-        self.query.is_match(&text)
+        true
     }
 }
 
@@ -109,3 +223,180 @@ impl Filter for QueryFilter {
 pub fn query(query: &str) -> QueryFilter {
     QueryFilter::new(query)
 }
+
+/// Query arguments.
+///
+/// The variables parsed by the last matched [`QueryFilter`], by name.
+///
+/// Taken by the handlers as an extractor, which fails with a [`QueryError`]
+/// If a value couldn't be converted to its type.
+///
+/// [`QueryFilter`]: crate::filters::QueryFilter
+/// [`QueryError`]: crate::filters::QueryError
+#[derive(Clone, Debug, Default)]
+pub struct QueryArgs {
+    values: HashMap<String, QueryValue>,
+}
+
+impl QueryArgs {
+    /// Get the value of the variable `name` as `T`.
+    ///
+    /// Returns `None` if it's missing, being optional, or of another type.
+    pub fn get<T: FromQueryValue>(&self, name: &str) -> Option<T> {
+        self.values.get(name).and_then(T::from_query_value)
+    }
+
+    /// Get the raw value of the variable `name`.
+    pub fn value(&self, name: &str) -> Option<&QueryValue> {
+        self.values.get(name)
+    }
+
+    /// Checks if the variable `name` was given.
+    pub fn contains(&self, name: &str) -> bool {
+        self.values.contains_key(name)
+    }
+}
+
+impl FromUpdate for QueryArgs {
+    fn from_update(_: &Client, _: &Update, _: &Data, context: &Context) -> Result<Option<Self>> {
+        if let Some(e) = context.get::<QueryError>() {
+            return Err(Error::filter(e.clone()));
+        }
+
+        Ok(context.get::<Self>().cloned())
+    }
+
+    /// Doesn't fail on the [`QueryError`], which is returned after the middlewares.
+    fn check(_: &Client, _: &Update, _: &Data, context: &Context) -> Result<bool> {
+        Ok(context.contains::<Self>() || context.contains::<QueryError>())
+    }
+}
+
+/// A query variable value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum QueryValue {
+    /// An `int`.
+    Int(i64),
+
+    /// A `float` or `double`.
+    Float(f64),
+
+    /// A `bool`: `true`, `false`, `yes`, `no`, `1` or `0`.
+    Bool(bool),
+
+    /// A `str`, `sym` or `all`.
+    Str(String),
+}
+
+/// Query error.
+///
+/// A query variable value couldn't be converted to its type, as an `int` out of range.
+#[derive(Clone, Debug)]
+pub struct QueryError {
+    var: String,
+    ty: &'static str,
+    value: String,
+}
+
+impl QueryError {
+    /// Get the variable name.
+    pub fn var(&self) -> &str {
+        &self.var
+    }
+
+    /// Get the given value.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the query variable `{}` must be `{}`, got `{}`",
+            self.var, self.ty, self.value
+        )
+    }
+}
+
+impl StdError for QueryError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(query: &str, text: &str) -> Option<std::result::Result<QueryArgs, QueryError>> {
+        QueryFilter::new(query).parse(text)
+    }
+
+    #[test]
+    fn parse_typed_vars() {
+        let args = parse(
+            "ban id:int days:float notify:bool reason:all",
+            "ban -42 1.5 Yes spam!",
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(args.get::<i64>("id"), Some(-42));
+        assert_eq!(args.get::<f64>("days"), Some(1.5));
+        assert_eq!(args.get::<bool>("notify"), Some(true));
+        assert_eq!(args.get::<String>("reason").as_deref(), Some("spam!"));
+        assert_eq!(args.get::<i64>("reason"), None);
+    }
+
+    #[test]
+    fn parse_only_typed_values() {
+        assert!(parse("ban id:int", "ban foo").is_none());
+        assert!(parse("ban id:int", "ban 12abc").is_none());
+        assert!(parse("mute on:bool", "mute maybe").is_none());
+        assert!(parse("wait secs:float", "wait .5").is_none());
+
+        let args = parse("wait secs:float", "wait 2").unwrap().unwrap();
+        assert_eq!(args.get::<f64>("secs"), Some(2.0));
+    }
+
+    #[test]
+    fn parse_optional_vars() {
+        let filter = QueryFilter::new("page n:int? sort:str:maybe");
+
+        let args = filter.parse("page").unwrap().unwrap();
+        assert!(!args.contains("n"));
+        assert!(!args.contains("sort"));
+
+        let args = filter.parse("page 2 name").unwrap().unwrap();
+        assert_eq!(args.get::<i64>("n"), Some(2));
+        assert_eq!(args.get::<String>("sort").as_deref(), Some("name"));
+
+        let args = filter.parse("page name").unwrap().unwrap();
+        assert!(!args.contains("n"));
+        assert_eq!(args.get::<String>("sort").as_deref(), Some("name"));
+    }
+
+    #[test]
+    fn parse_out_of_range_int() {
+        let e = parse("ban id:int", "ban 99999999999999999999")
+            .unwrap()
+            .unwrap_err();
+
+        assert_eq!(e.var(), "id");
+        assert_eq!(e.value(), "99999999999999999999");
+        assert_eq!(
+            e.to_string(),
+            "the query variable `id` must be `int`, got `99999999999999999999`"
+        );
+    }
+
+    #[test]
+    fn try_new_rejects_bad_queries() {
+        let e = QueryFilter::try_new("ban id:number").err().unwrap();
+        assert_eq!(
+            e.to_string(),
+            "unknown type `number` of the query variable `id`"
+        );
+
+        assert!(QueryFilter::try_new("move n:int n:int").is_err());
+        assert!(QueryFilter::try_new("ban user-id:int").is_err());
+    }
+}
@@ -10,7 +10,10 @@ use async_trait::async_trait;
 use grammers_client::{Client, Update};
 use regex::Regex;
 
-use crate::traits::{Filter, GetMessage, GetQuery};
+use crate::{
+    traits::{Filter, GetMessage, GetQuery},
+    Context,
+};
 
 /// Regex filter.
 ///
@@ -30,7 +33,7 @@ impl RegexFilter {
 
 #[async_trait]
 impl Filter for RegexFilter {
-    async fn is_ok(&mut self, _client: &Client, update: &Update) -> bool {
+    async fn is_ok(&self, _client: &Client, update: &Update, _context: &mut Context) -> bool {
         let message = update.get_message();
         let query = update.get_query();
 
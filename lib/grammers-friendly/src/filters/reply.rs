@@ -9,7 +9,10 @@
 use async_trait::async_trait;
 use grammers_client::{Client, Update};
 
-use crate::traits::{Filter, GetMessage};
+use crate::{
+    traits::{Filter, GetMessage},
+    Context,
+};
 
 /// Reply filter.
 ///
@@ -20,7 +23,7 @@ pub struct ReplyFilter;
 
 #[async_trait]
 impl Filter for ReplyFilter {
-    async fn is_ok(&mut self, _client: &Client, update: &Update) -> bool {
+    async fn is_ok(&self, _client: &Client, update: &Update, _context: &mut Context) -> bool {
         let message = update.get_message();
 
         if let Some(message) = message {
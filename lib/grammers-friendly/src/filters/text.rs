@@ -9,7 +9,10 @@
 use async_trait::async_trait;
 use grammers_client::{Client, Update};
 
-use crate::traits::{Filter, GetMessage};
+use crate::{
+    traits::{Filter, GetMessage},
+    Context,
+};
 
 /// Text filter.
 ///
@@ -27,7 +30,7 @@ impl TextFilter {
 
 #[async_trait]
 impl Filter for TextFilter {
-    async fn is_ok(&mut self, _client: &Client, update: &Update) -> bool {
+    async fn is_ok(&self, _client: &Client, update: &Update, _context: &mut Context) -> bool {
         let message = update.get_message();
 
         if let Some(message) = message {
@@ -0,0 +1,75 @@
+// Copyright (C) 2024 AndrielFR
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/// Flow.
+///
+/// Decides whether an update keeps being propagated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Flow {
+    /// Pass the update to the next `handler` or `router`.
+    Continue,
+
+    /// Stop here, the update is handled.
+    #[default]
+    Stop,
+}
+
+/// Propagation.
+///
+/// What a [`Router`] does after one of its `handlers` matches an update,
+/// When the handler doesn't return a [`Flow`] by itself.
+///
+/// [`Router`]: crate::Router
+/// [`Flow`]: crate::Flow
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Propagation {
+    /// Stop at the first matched handler, even if it fails.
+    StopOnMatch,
+
+    /// Stop at the first matched handler, unless it fails (default).
+    #[default]
+    ContinueOnError,
+
+    /// Run all the matched handlers.
+    ContinueAlways,
+}
+
+impl Propagation {
+    /// The flow after a handler succeeds.
+    pub fn on_success(&self) -> Flow {
+        match self {
+            Self::StopOnMatch | Self::ContinueOnError => Flow::Stop,
+            Self::ContinueAlways => Flow::Continue,
+        }
+    }
+
+    /// The flow after a handler fails, without error handler.
+    pub fn on_error(&self) -> Flow {
+        match self {
+            Self::StopOnMatch => Flow::Stop,
+            Self::ContinueOnError | Self::ContinueAlways => Flow::Continue,
+        }
+    }
+}
+
+/// Veto.
+///
+/// What a [`Router`] does when a before-type middleware stops an update,
+/// Returning [`Flow::Stop`].
+///
+/// [`Router`]: crate::Router
+/// [`Flow::Stop`]: crate::Flow::Stop
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Veto {
+    /// The update is handled, nothing else runs (default).
+    #[default]
+    Handled,
+
+    /// The handler is skipped, the next ones may still handle the update.
+    Unhandled,
+}
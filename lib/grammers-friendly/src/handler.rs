@@ -6,14 +6,16 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::sync::Arc;
+use std::{marker::PhantomData, sync::Arc};
 
+use futures_util::future::BoxFuture;
 use grammers_client::{Client, Update};
-use tokio::sync::Mutex;
 
 use crate::{
-    traits::{AsyncFnCallback, Filter},
-    Data, Middleware, MiddlewareType,
+    filter,
+    middleware::{Endpoint, Middlewares},
+    traits::{AsyncFn, AsyncFnCallback, Filter},
+    utils, CommandInfo, Context, Data, Error, Flow, Next, Result, UsageError, Veto,
 };
 
 /// A Handler.
@@ -21,83 +23,203 @@ use crate::{
 /// Will be runned after before-type `middlewares`.
 #[derive(Clone)]
 pub struct Handler {
-    func: Arc<dyn AsyncFnCallback>,
+    func: Arc<dyn HandlerFn>,
     filter: Box<dyn Filter>,
     update_type: UpdateType,
+
+    description: Option<String>,
+    usage: Option<String>,
+    category: Option<String>,
+    hidden: Option<bool>,
 }
 
 impl Handler {
     /// Construct a new handler.
     ///
-    /// Receives a [`UpdateType`], `Fn(&mut Client, &mut Update, &mut Data)` and its filter.
+    /// Receives a [`UpdateType`], the `function` and its filter.
+    ///
+    /// The `function` is `Fn(&mut Client, &mut Update, &mut Data, &mut Context)` or takes
+    /// Any combination of [`extract`]ors, being skipped if one of them is missing.
+    ///
+    /// The extractors are checked after the filter, before any middleware: a missing one skips
+    /// The handler without running them, a failing one fails it. Only [`extract::Args`] wait
+    /// For the before-type middlewares, which may insert them.
+    ///
+    /// The `function` returns `Result<()>` or, to override the router's [`Propagation`],
+    /// `Result<Flow>`.
     ///
     /// [`UpdateType`]: crate::UpdateType
-    pub fn new<A: AsyncFnCallback, F: Filter>(update_type: UpdateType, func: A, filter: F) -> Self {
+    /// [`extract`]: crate::extract
+    /// [`extract::Args`]: crate::extract::Args
+    /// [`Propagation`]: crate::Propagation
+    pub fn new<A, M, F>(update_type: UpdateType, func: A, filter: F) -> Self
+    where
+        A: AsyncFnCallback<M>,
+        M: 'static,
+        F: Filter,
+    {
         Self {
-            func: Arc::new(func),
+            func: Arc::new(Erased {
+                func,
+                marker: PhantomData,
+            }),
             filter: Box::new(filter),
             update_type,
+
+            description: None,
+            usage: <A as AsyncFn<'static, M>>::usage(),
+            category: None,
+            hidden: None,
         }
     }
 
     /// Construct a new handler with `NewMessage` update type.
     ///
-    /// Receives a Fn(&mut Client, &mut Update, &mut Data)` and its filter.
-    pub fn new_message<A: AsyncFnCallback, F: Filter>(func: A, filter: F) -> Self {
+    /// Receives the `function`, see [`Handler::new`], and its filter.
+    pub fn new_message<A, M, F>(func: A, filter: F) -> Self
+    where
+        A: AsyncFnCallback<M>,
+        M: 'static,
+        F: Filter,
+    {
         Self::new(UpdateType::NewMessage, func, filter)
     }
 
     /// Construct a new handler with `MessageEdited` update type.
     ///
-    /// Receives a Fn(&mut Client, &mut Update, &mut Data)` and its filter.
-    pub fn message_edited<A: AsyncFnCallback, F: Filter>(func: A, filter: F) -> Self {
+    /// Receives the `function`, see [`Handler::new`], and its filter.
+    pub fn message_edited<A, M, F>(func: A, filter: F) -> Self
+    where
+        A: AsyncFnCallback<M>,
+        M: 'static,
+        F: Filter,
+    {
         Self::new(UpdateType::MessageEdited, func, filter)
     }
 
     /// Construct a new handler with `MessageDeleted` update type.
     ///
-    /// Receives a Fn(&mut Client, &mut Update, &mut Data)` and its filter.
-    pub fn message_deleted<A: AsyncFnCallback, F: Filter>(func: A, filter: F) -> Self {
+    /// Receives the `function`, see [`Handler::new`], and its filter.
+    pub fn message_deleted<A, M, F>(func: A, filter: F) -> Self
+    where
+        A: AsyncFnCallback<M>,
+        M: 'static,
+        F: Filter,
+    {
         Self::new(UpdateType::MessageDeleted, func, filter)
     }
 
     /// Construct a new handler with `CallbackQuery` update type.
     ///
-    /// Receives a Fn(&mut Client, &mut Update, &mut Data)` and its filter.
-    pub fn callback_query<A: AsyncFnCallback, F: Filter>(func: A, filter: F) -> Self {
+    /// Receives the `function`, see [`Handler::new`], and its filter.
+    pub fn callback_query<A, M, F>(func: A, filter: F) -> Self
+    where
+        A: AsyncFnCallback<M>,
+        M: 'static,
+        F: Filter,
+    {
         Self::new(UpdateType::CallbackQuery, func, filter)
     }
 
     /// Construct a new handler with `InlineQuery` update type.
     ///
-    /// Receives a Fn(&mut Client, &mut Update, &mut Data)` and its filter.
-    pub fn inline_query<A: AsyncFnCallback, F: Filter>(func: A, filter: F) -> Self {
+    /// Receives the `function`, see [`Handler::new`], and its filter.
+    pub fn inline_query<A, M, F>(func: A, filter: F) -> Self
+    where
+        A: AsyncFnCallback<M>,
+        M: 'static,
+        F: Filter,
+    {
         Self::new(UpdateType::InlineQuery, func, filter)
     }
 
     /// Construct a new handler with `Raw` update type.
     ///
-    /// Receives a Fn(&mut Client, &mut Update, &mut Data)` and its filter.
-    pub fn raw<A: AsyncFnCallback, F: Filter>(func: A, filter: F) -> Self {
+    /// Receives the `function`, see [`Handler::new`], and its filter.
+    pub fn raw<A, M, F>(func: A, filter: F) -> Self
+    where
+        A: AsyncFnCallback<M>,
+        M: 'static,
+        F: Filter,
+    {
         Self::new(UpdateType::Raw, func, filter)
     }
 
+    /// Set the description of the handler commands, shown in the help.
+    ///
+    /// Overrides the one of the filter.
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Set the category of the handler commands, which groups them in the help.
+    ///
+    /// Overrides the one of the filter.
+    pub fn category(mut self, category: impl Into<String>) -> Self {
+        self.category = Some(category.into());
+        self
+    }
+
+    /// Set if the handler commands are hidden from the help and the bot command menu.
+    ///
+    /// Overrides the one of the filter.
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.hidden = Some(hidden);
+        self
+    }
+
+    /// Get the commands matched by the handler filter.
+    ///
+    /// Without a usage set by the filter, it is taken from the [`extract::Command`] of the
+    /// `function`, if any.
+    ///
+    /// [`extract::Command`]: crate::extract::Command
+    pub fn commands(&self) -> Vec<CommandInfo> {
+        let mut commands = self.filter.commands();
+
+        commands.iter_mut().for_each(|command| {
+            if let Some(description) = self.description.as_ref() {
+                command.set_description(description.clone());
+            }
+            if let Some(category) = self.category.as_ref() {
+                command.set_category(category.clone());
+            }
+            if let Some(hidden) = self.hidden {
+                command.set_hidden(hidden);
+            }
+            if command.usage().is_none() {
+                if let Some(usage) = self.usage.as_ref() {
+                    command.set_usage(usage.clone());
+                }
+            }
+        });
+
+        commands
+    }
+
     /// Handle the update.
     ///
     /// First checks if [`UpdateType`] match,
-    /// So, checks if its `filter` match and
-    /// Lastly, if all ok, run the `function`.
+    /// So, checks if its `filter` and the `function` extractors match and
+    /// Lastly, if all ok, run the `function` wrapped by the middlewares.
+    ///
+    /// If the extractors are missing, no middleware is runned.
+    ///
+    /// Return the [`Outcome`] or the `function` error.
     ///
-    /// Return `True` if handled or `False` otherwise.
+    /// [`Outcome`]: crate::Outcome
     ///
     /// [`UpdateType`]: crate::UpdateType
-    pub async fn handle(
-        &mut self,
+    pub(crate) async fn handle(
+        &self,
         client: &mut Client,
         update: &mut Update,
         data: &mut Data,
-        middlewares: &mut Vec<Arc<Mutex<Middleware>>>,
-    ) -> bool {
+        context: &mut Context,
+        middlewares: &Middlewares,
+        veto: Veto,
+    ) -> Result<Outcome> {
         if matches!(self.update_type, UpdateType::NewMessage)
             && matches!(update, Update::NewMessage(_))
             || matches!(self.update_type, UpdateType::MessageEdited)
@@ -110,38 +232,136 @@ impl Handler {
                 && matches!(update, Update::InlineQuery(_))
             || matches!(self.update_type, UpdateType::Raw)
         {
-            if !self.filter.is_ok(&*client, &*update).await {
-                return false;
-            }
+            let Some(snapshot) =
+                filter::check_all(&[&*self.filter], &*client, &*update, context).await
+            else {
+                return Ok(Outcome::Skipped);
+            };
 
-            for middleware in middlewares
-                .iter_mut()
-                .filter(|m| m.try_lock().unwrap().mtype() == MiddlewareType::Before)
-            {
-                let mut mid = middleware.try_lock().unwrap();
-                mid.call(client, update, data).await;
+            match self.func.check(&*client, &*update, &*data, &*context) {
+                Ok(true) => {}
+                Ok(false) => {
+                    *context = snapshot;
+                    return Ok(Outcome::Skipped);
+                }
+                Err(e) => return self.fail(e, update).await,
             }
 
-            if let Err(e) = self.func.call(client, update, data).await {
-                log::error!("Error while running handler: {}", e);
-                return false;
-            }
+            return Next::new(middlewares, veto, Endpoint::Handler(self))
+                .run(client, update, data, context)
+                .await;
+        }
 
-            for middleware in middlewares
-                .iter_mut()
-                .filter(|m| m.try_lock().unwrap().mtype() == MiddlewareType::After)
-            {
-                let mut mid = middleware.try_lock().unwrap();
-                mid.call(client, update, data).await;
-            }
+        Ok(Outcome::Skipped)
+    }
+
+    /// Run the `function`.
+    ///
+    /// The end of the [`Next`] chain.
+    ///
+    /// If the command arguments couldn't be parsed, replies the [`UsageError`] instead.
+    /// The other errors are wrapped into [`Error::Handler`], but the invocation and filter ones.
+    ///
+    /// [`Next`]: crate::Next
+    /// [`UsageError`]: crate::UsageError
+    /// [`Error::Handler`]: crate::Error::Handler
+    pub(crate) async fn call(
+        &self,
+        client: &mut Client,
+        update: &mut Update,
+        data: &mut Data,
+        context: &mut Context,
+    ) -> Result<Outcome> {
+        match self.func.call(client, update, data, context).await {
+            Err(e) => self.fail(e, update).await,
+            result => result,
+        }
+    }
 
-            return true;
+    /// Handle the `function` error.
+    async fn fail(&self, error: Error, update: &Update) -> Result<Outcome> {
+        match (
+            error.downcast_ref::<UsageError>(),
+            utils::get_message(update),
+        ) {
+            (Some(usage), Some(message)) => {
+                message.reply(usage.to_string()).await?;
+                Ok(Outcome::Handled(None))
+            }
+            _ => Err(match error {
+                Error::Other(e) => Error::Handler(e),
+                e => e,
+            }),
         }
+    }
+}
 
-        false
+/// The `function` of a handler, whatever its signature.
+trait HandlerFn: Send + Sync + 'static {
+    fn call<'a>(
+        &'a self,
+        client: &'a mut Client,
+        update: &'a mut Update,
+        data: &'a mut Data,
+        context: &'a mut Context,
+    ) -> BoxFuture<'a, Result<Outcome>>;
+
+    fn check(
+        &self,
+        client: &Client,
+        update: &Update,
+        data: &Data,
+        context: &Context,
+    ) -> Result<bool>;
+}
+
+struct Erased<A, M> {
+    func: A,
+    marker: PhantomData<fn() -> M>,
+}
+
+impl<A, M> HandlerFn for Erased<A, M>
+where
+    A: AsyncFnCallback<M>,
+    M: 'static,
+{
+    fn call<'a>(
+        &'a self,
+        client: &'a mut Client,
+        update: &'a mut Update,
+        data: &'a mut Data,
+        context: &'a mut Context,
+    ) -> BoxFuture<'a, Result<Outcome>> {
+        AsyncFn::<'a, M>::call(&self.func, client, update, data, context)
+    }
+
+    fn check(
+        &self,
+        client: &Client,
+        update: &Update,
+        data: &Data,
+        context: &Context,
+    ) -> Result<bool> {
+        AsyncFn::<'_, M>::check(&self.func, client, update, data, context)
     }
 }
 
+/// Outcome.
+///
+/// What a [`Handler`] did with the update.
+///
+/// [`Handler`]: crate::Handler
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The update type, the filter or the `function` extractors didn't match.
+    Skipped,
+
+    /// The `function` was runned, with the [`Flow`] it returned, if any.
+    ///
+    /// [`Flow`]: crate::Flow
+    Handled(Option<Flow>),
+}
+
 /// Update Type.
 ///
 /// In thesis, you don't need to use this,
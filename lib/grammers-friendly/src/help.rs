@@ -0,0 +1,220 @@
+// Copyright (C) 2024 AndrielFR
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::HashMap;
+
+use grammers_client::types::Message;
+
+use crate::{
+    extract, filters::CommandFilter, traits::Module, Account, CommandArgs, CommandInfo, Handler,
+    Result, Router,
+};
+
+/// Max of commands shown per page.
+const PER_PAGE: usize = 10;
+
+/// Help.
+///
+/// The help of the commands, grouped by category, without the hidden ones.
+///
+/// Added by the dispatcher as module, with the commands of all its routers
+/// And the ones of each account, see [`Help::with_account`].
+#[derive(Clone)]
+pub struct Help {
+    commands: Vec<CommandInfo>,
+    per_page: usize,
+    accounts: HashMap<String, Help>,
+}
+
+impl Help {
+    /// Construct a new help.
+    ///
+    /// Receives the commands, see [`Dispatcher::commands`].
+    ///
+    /// [`Dispatcher::commands`]: crate::Dispatcher::commands
+    pub fn new(commands: Vec<CommandInfo>) -> Self {
+        let mut commands = commands
+            .into_iter()
+            .filter(|command| !command.is_hidden())
+            .collect::<Vec<_>>();
+
+        let mut categories = Vec::<String>::new();
+        commands.iter().for_each(|command| {
+            if let Some(category) = command.category() {
+                if !categories.iter().any(|c| c == category) {
+                    categories.push(category.to_string());
+                }
+            }
+        });
+
+        // Uncategorized first, then in the order they were added
+        commands.sort_by_key(|command| {
+            command
+                .category()
+                .and_then(|category| categories.iter().position(|c| c == category))
+        });
+
+        Self {
+            commands,
+            per_page: PER_PAGE,
+            accounts: HashMap::new(),
+        }
+    }
+
+    /// Set the commands shown to the account `name`, instead of all of them.
+    ///
+    /// See [`Dispatcher::commands_for`].
+    ///
+    /// [`Dispatcher::commands_for`]: crate::Dispatcher::commands_for
+    pub fn with_account(mut self, name: impl Into<String>, commands: Vec<CommandInfo>) -> Self {
+        let help = Help::new(commands).per_page(self.per_page);
+        self.accounts.insert(name.into(), help);
+        self
+    }
+
+    /// Set the max of commands shown per page.
+    ///
+    /// By default, is `10`.
+    pub fn per_page(mut self, per_page: usize) -> Self {
+        self.per_page = per_page.max(1);
+        self.accounts
+            .values_mut()
+            .for_each(|help| help.per_page = self.per_page);
+        self
+    }
+
+    /// Get the help of the account `name`, or this one if it has no own.
+    pub fn account(&self, name: &str) -> &Help {
+        self.accounts.get(name).unwrap_or(self)
+    }
+
+    /// Get the commands shown.
+    pub fn commands(&self) -> &[CommandInfo] {
+        &self.commands
+    }
+
+    /// Get the number of pages.
+    pub fn pages(&self) -> usize {
+        self.commands.len().div_ceil(self.per_page).max(1)
+    }
+
+    /// Get the `page`, starting from `1`.
+    ///
+    /// Returns `None` if it doesn't exist.
+    pub fn page(&self, page: usize) -> Option<String> {
+        if page == 0 || page > self.pages() {
+            return None;
+        }
+
+        let mut text = format!("Commands ({}/{})\n", page, self.pages());
+        let mut category = None;
+
+        self.commands
+            .iter()
+            .skip((page - 1) * self.per_page)
+            .take(self.per_page)
+            .enumerate()
+            .for_each(|(pos, command)| {
+                if command.category() != category {
+                    category = command.category();
+                    if pos > 0 {
+                        text.push('\n');
+                    }
+                    text.push_str(&format!("\n{}", category.unwrap_or_default()));
+                }
+
+                text.push_str(&format!("\n{}", usage(command)));
+                if let Some(description) = command.description() {
+                    text.push_str(&format!(" - {}", description));
+                }
+            });
+
+        if self.commands.is_empty() {
+            text.push_str("\nNo commands.");
+        }
+
+        Some(text)
+    }
+
+    /// Get the detail of the command called `name`, or with it as alias.
+    ///
+    /// Returns `None` if it doesn't exist.
+    pub fn command(&self, name: &str) -> Option<String> {
+        let name = name.trim_start_matches(|c: char| !c.is_alphanumeric());
+        let command = self
+            .commands
+            .iter()
+            .find(|command| command.is_named(name))?;
+
+        let mut text = usage(command);
+        if let Some(description) = command.description() {
+            text.push_str(&format!("\n\n{}", description));
+        }
+
+        if !command.aliases().is_empty() {
+            let prefix = command.prefix().map(String::from).unwrap_or_default();
+            let aliases = command
+                .aliases()
+                .iter()
+                .map(|alias| format!("{}{}", prefix, alias))
+                .collect::<Vec<_>>();
+
+            text.push_str(&format!("\n\nAliases: {}", aliases.join(", ")));
+        }
+        if let Some(category) = command.category() {
+            text.push_str(&format!("\nCategory: {}", category));
+        }
+
+        Some(text)
+    }
+
+    /// Construct a router with the `help` command.
+    ///
+    /// `help` replies the first page, `help 2` the second and `help ban`
+    /// The detail of `ban`, with the commands of the account which received it.
+    pub fn router(prefixes: &str) -> Router {
+        let filter = CommandFilter::new(prefixes, "help")
+            .description("Show the commands")
+            .usage("[page|command]");
+
+        Router::default().add_handler(Handler::new_message(help, filter))
+    }
+}
+
+impl Module for Help {}
+
+/// Get the command with its usage, as `/ban <user>`.
+fn usage(command: &CommandInfo) -> String {
+    match command.usage() {
+        Some(usage) => format!("{} {}", command.command(), usage),
+        None => command.command(),
+    }
+}
+
+/// Reply the help.
+async fn help(
+    message: Message,
+    args: CommandArgs,
+    account: Account,
+    extract::Module(help): extract::Module<Help>,
+) -> Result<()> {
+    let help = help.account(account.name());
+    let text = match args.get(0) {
+        Some(arg) => match arg.parse() {
+            Ok(page) => help.page(page),
+            Err(_) => help.command(arg),
+        },
+        None => help.page(1),
+    };
+
+    message
+        .reply(text.unwrap_or_else(|| "Nothing found.".to_string()))
+        .await?;
+
+    Ok(())
+}
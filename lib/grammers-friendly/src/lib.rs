@@ -6,30 +6,55 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+mod account;
+mod command;
+mod context;
 mod data;
 mod dispatcher;
+mod error;
+pub mod extract;
 mod filter;
 pub mod filters;
+mod flow;
 mod handler;
+mod help;
+mod metrics;
 mod middleware;
+mod retry;
 mod router;
+mod shutdown;
+pub mod sources;
 pub mod traits;
 pub mod utils;
 
-pub use data::Data;
-pub use dispatcher::Dispatcher;
-pub use handler::{Handler, UpdateType};
-pub use middleware::{Middleware, MiddlewareType};
+pub use account::{Account, DEFAULT_ACCOUNT};
+pub use command::{
+    ArgError, CommandArgs, CommandInfo, CommandScope, PendingArgs, Rest, UsageError, UserRef,
+};
+pub use context::Context;
+pub use data::{Data, Dependency, State};
+pub use dispatcher::{DispatchStrategy, Dispatcher, QueueOverflow};
+pub use error::{DispatcherError, Error, Result};
+pub use flow::{Flow, Propagation, Veto};
+pub use handler::{Handler, Outcome, UpdateType};
+pub use help::Help;
+pub use metrics::Metrics;
+pub use middleware::{Middleware, MiddlewareType, Next};
+pub use retry::RetryPolicy;
 pub use router::Router;
+pub use shutdown::{ShutdownHandle, StopReason};
 
 #[cfg(feature = "macros")]
 pub use grammers_macros as macros;
+#[cfg(feature = "macros")]
+pub use grammers_macros::CommandArgs;
 
 pub mod prelude {
     pub use crate::traits::*;
     pub use crate::{
-        filters, utils, Data, Dispatcher, Handler, Middleware, MiddlewareType::*, Router,
-        UpdateType::*,
+        extract, filters, utils, Account, CommandArgs, Context, Data, Dependency, DispatchStrategy,
+        Dispatcher, Flow, Handler, Middleware, MiddlewareType::*, Next, Propagation, QueueOverflow,
+        Router, State, UpdateType::*, Veto,
     };
 
     #[cfg(feature = "macros")]
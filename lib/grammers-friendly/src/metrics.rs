@@ -0,0 +1,60 @@
+// Copyright (C) 2024 AndrielFR
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Dispatcher metrics.
+///
+/// Live counters updated by the dispatcher while it runs.
+///
+/// Get it through [`Dispatcher::metrics`].
+///
+/// [`Dispatcher::metrics`]: crate::Dispatcher::metrics
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub(crate) in_flight: AtomicUsize,
+    pub(crate) queued: AtomicUsize,
+    pub(crate) active_chats: AtomicUsize,
+    pub(crate) backpressure_waits: AtomicU64,
+    pub(crate) dropped_updates: AtomicU64,
+}
+
+impl Metrics {
+    /// Updates being handled right now.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// Updates waiting in the per-chat queues, including the ones being handled.
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    /// Chats with at least one pending update.
+    pub fn active_chats(&self) -> usize {
+        self.active_chats.load(Ordering::Relaxed)
+    }
+
+    /// How many times the dispatcher had to wait for room in a full chat queue.
+    ///
+    /// See [`QueueOverflow::Wait`].
+    ///
+    /// [`QueueOverflow::Wait`]: crate::QueueOverflow::Wait
+    pub fn backpressure_waits(&self) -> u64 {
+        self.backpressure_waits.load(Ordering::Relaxed)
+    }
+
+    /// Updates dropped because their chat queue was full.
+    ///
+    /// See [`QueueOverflow::Drop`].
+    ///
+    /// [`QueueOverflow::Drop`]: crate::QueueOverflow::Drop
+    pub fn dropped_updates(&self) -> u64 {
+        self.dropped_updates.load(Ordering::Relaxed)
+    }
+}
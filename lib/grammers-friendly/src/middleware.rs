@@ -6,18 +6,30 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use std::sync::Arc;
+
+use futures_util::future::BoxFuture;
 use grammers_client::{Client, Update};
 
-use crate::{traits::MiddlewareImpl, Data};
+use crate::{
+    traits::{AroundMiddlewareImpl, ErrorHandlerCallback, MiddlewareImpl},
+    Account, Context, Data, Flow, Handler, Outcome, Result, Router, Veto,
+};
 
 /// A Middleware.
 ///
-/// Can be before-type of after-type, which implies that it will be runned before
-/// Or after the handlers.
+/// Can be before-type, after-type or around-type, which implies that it will be runned
+/// Before, after or around the handlers.
 #[derive(Clone)]
 pub struct Middleware {
-    pub mid: Box<dyn MiddlewareImpl>,
-    pub mtype: MiddlewareType,
+    kind: Kind,
+}
+
+#[derive(Clone)]
+enum Kind {
+    Before(Arc<dyn MiddlewareImpl>),
+    After(Arc<dyn MiddlewareImpl>),
+    Around(Arc<dyn AroundMiddlewareImpl>),
 }
 
 impl Middleware {
@@ -25,12 +37,14 @@ impl Middleware {
     ///
     /// Receives a struct which implements [`MiddlewareImpl`] and [`MiddlewareType`].
     ///
+    /// The around-type ones are constructed with [`Middleware::around`].
+    ///
     /// [`MiddlewareImpl`]: crate::traits::MiddlewareImpl
     /// [`MiddlewareType`]: crate::MiddlewareType
     pub fn new<M: MiddlewareImpl>(mid: M, mtype: MiddlewareType) -> Self {
-        Self {
-            mid: Box::new(mid),
-            mtype,
+        match mtype {
+            MiddlewareType::Before => Self::before(mid),
+            MiddlewareType::After => Self::after(mid),
         }
     }
 
@@ -41,8 +55,7 @@ impl Middleware {
     /// [`MiddlewareImpl`]: crate::traits::MiddlewareImpl
     pub fn before<M: MiddlewareImpl>(mid: M) -> Self {
         Self {
-            mid: Box::new(mid),
-            mtype: MiddlewareType::Before,
+            kind: Kind::Before(Arc::new(mid)),
         }
     }
 
@@ -53,21 +66,50 @@ impl Middleware {
     /// [`MiddlewareImpl`]: crate::traits::MiddlewareImpl
     pub fn after<M: MiddlewareImpl>(mid: M) -> Self {
         Self {
-            mid: Box::new(mid),
-            mtype: MiddlewareType::After,
+            kind: Kind::After(Arc::new(mid)),
         }
     }
 
-    /// Run the middleware.
-    pub(crate) async fn call(&mut self, client: &mut Client, update: &mut Update, data: &mut Data) {
-        if let Err(e) = self.mid.call(client, update, data).await {
-            log::error!("Error while running middleware: {:?}", e);
+    /// Construct a new around-type `Middleware`.
+    ///
+    /// Receives a struct which implements [`AroundMiddlewareImpl`].
+    ///
+    /// [`AroundMiddlewareImpl`]: crate::traits::AroundMiddlewareImpl
+    pub fn around<M: AroundMiddlewareImpl>(mid: M) -> Self {
+        Self {
+            kind: Kind::Around(Arc::new(mid)),
+        }
+    }
+
+    /// Get the before-type or after-type middleware.
+    ///
+    /// Returns `None` if it is around-type.
+    pub fn mid(&self) -> Option<&dyn MiddlewareImpl> {
+        match &self.kind {
+            Kind::Before(mid) | Kind::After(mid) => Some(mid.as_ref()),
+            Kind::Around(_) => None,
+        }
+    }
+
+    /// Get the around-type middleware.
+    ///
+    /// Returns `None` if it is before-type or after-type.
+    pub fn around_mid(&self) -> Option<&dyn AroundMiddlewareImpl> {
+        match &self.kind {
+            Kind::Around(mid) => Some(mid.as_ref()),
+            _ => None,
         }
     }
 
     /// Get the middleware type.
-    pub fn mtype(&self) -> MiddlewareType {
-        self.mtype.clone()
+    ///
+    /// Returns `None` if it is around-type.
+    pub fn mtype(&self) -> Option<MiddlewareType> {
+        match self.kind {
+            Kind::Before(_) => Some(MiddlewareType::Before),
+            Kind::After(_) => Some(MiddlewareType::After),
+            Kind::Around(_) => None,
+        }
     }
 }
 
@@ -76,12 +118,239 @@ impl Middleware {
 /// In thesis, you don't need to use this,
 /// Just use [`Middleware`] constructors: `::before(...)`, and/or `::after(...)`.
 ///
+/// The around-type ones have their own constructor, [`Middleware::around`].
+///
 /// [`Middleware`]: crate::Middleware
+/// [`Middleware::around`]: crate::Middleware::around
 #[derive(Clone, PartialEq)]
 pub enum MiddlewareType {
     /// Runned before any `handler` in the same `router`.
+    ///
+    /// May veto the update, see [`MiddlewareImpl`].
+    ///
+    /// [`MiddlewareImpl`]: crate::traits::MiddlewareImpl
     Before,
 
     /// Runned after all `handlers` in the same `router`.
     After,
 }
+
+/// Next.
+///
+/// The rest of the chain wrapped by an around-type middleware: the next around-type
+/// Middlewares and the handler, or the routing for the outer ones, with the before-type
+/// And after-type middlewares.
+///
+/// Can be copied to run the chain more than once, as to retry the handler.
+#[derive(Clone, Copy)]
+pub struct Next<'a> {
+    around: &'a [Arc<dyn AroundMiddlewareImpl>],
+    middlewares: &'a Middlewares,
+    veto: Veto,
+    endpoint: Endpoint<'a>,
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(middlewares: &'a Middlewares, veto: Veto, endpoint: Endpoint<'a>) -> Self {
+        Self {
+            around: &middlewares.around,
+            middlewares,
+            veto,
+            endpoint,
+        }
+    }
+
+    /// Run the rest of the chain.
+    ///
+    /// Returns the handler's [`Outcome`] or its error.
+    ///
+    /// If a before-type middleware vetoes the update, the [`Veto`] decides the outcome.
+    ///
+    /// The after-type middlewares aren't runned if the handler is skipped, unlike the
+    /// Outer ones, which are always runned after the routing, even if nothing handled it.
+    ///
+    /// [`Outcome`]: crate::Outcome
+    /// [`Veto`]: crate::Veto
+    pub async fn run(
+        self,
+        client: &mut Client,
+        update: &mut Update,
+        data: &mut Data,
+        context: &mut Context,
+    ) -> Result<Outcome> {
+        if let Some((middleware, around)) = self.around.split_first() {
+            let next = Self { around, ..self };
+            return middleware.call(client, update, data, context, next).await;
+        }
+
+        let middlewares = self.middlewares;
+        if middlewares.run_before(client, update, data, context).await == Flow::Stop {
+            return Ok(match self.veto {
+                Veto::Handled => Outcome::Handled(Some(Flow::Stop)),
+                Veto::Unhandled => Outcome::Skipped,
+            });
+        }
+
+        let outcome = self
+            .endpoint
+            .run(client, update, data, context, self.veto)
+            .await?;
+        if outcome == Outcome::Skipped && matches!(self.endpoint, Endpoint::Handler(_)) {
+            return Ok(outcome);
+        }
+
+        middlewares.run_after(client, update, data, context).await;
+
+        Ok(outcome)
+    }
+}
+
+/// What the middlewares are wrapping.
+#[derive(Clone, Copy)]
+pub(crate) enum Endpoint<'a> {
+    /// A handler `function`.
+    Handler(&'a Handler),
+
+    /// The routing of a router.
+    Router {
+        router: &'a Router,
+        account: &'a Account,
+        error_handler: Option<&'a Arc<dyn ErrorHandlerCallback>>,
+    },
+
+    /// The routing of the dispatcher.
+    Routers {
+        routers: &'a [Router],
+        account: &'a Account,
+        error_handler: Option<&'a Arc<dyn ErrorHandlerCallback>>,
+    },
+}
+
+impl<'a> Endpoint<'a> {
+    /// Run the endpoint.
+    ///
+    /// Boxed, as the routing runs [`Next`] chains itself.
+    ///
+    /// [`Next`]: crate::Next
+    fn run<'b>(
+        self,
+        client: &'b mut Client,
+        update: &'b mut Update,
+        data: &'b mut Data,
+        context: &'b mut Context,
+        veto: Veto,
+    ) -> BoxFuture<'b, Result<Outcome>>
+    where
+        'a: 'b,
+    {
+        Box::pin(async move {
+            let handled = match self {
+                Self::Handler(handler) => return handler.call(client, update, data, context).await,
+                Self::Router {
+                    router,
+                    account,
+                    error_handler,
+                } => {
+                    router
+                        .route(client, update, data, context, account, error_handler, veto)
+                        .await
+                }
+                Self::Routers {
+                    routers,
+                    account,
+                    error_handler,
+                } => {
+                    let mut handled = false;
+
+                    for router in routers.iter() {
+                        if router
+                            .handle_update(client, update, context, account, error_handler, veto)
+                            .await
+                        {
+                            handled = true;
+                            break;
+                        }
+                    }
+
+                    handled
+                }
+            };
+
+            Ok(match handled {
+                true => Outcome::Handled(Some(Flow::Stop)),
+                false => Outcome::Skipped,
+            })
+        })
+    }
+}
+
+/// The middlewares of a router, split by type.
+#[derive(Clone, Default)]
+pub(crate) struct Middlewares {
+    before: Vec<Arc<dyn MiddlewareImpl>>,
+    after: Vec<Arc<dyn MiddlewareImpl>>,
+    around: Vec<Arc<dyn AroundMiddlewareImpl>>,
+}
+
+impl Middlewares {
+    /// Attach a new middleware to the list of its type.
+    pub(crate) fn push(&mut self, middleware: Middleware) {
+        match middleware.kind {
+            Kind::Before(mid) => self.before.push(mid),
+            Kind::After(mid) => self.after.push(mid),
+            Kind::Around(mid) => self.around.push(mid),
+        }
+    }
+
+    /// Attach the middlewares of `other`, after the current ones.
+    pub(crate) fn extend(&mut self, other: &Middlewares) {
+        self.before.extend(other.before.iter().cloned());
+        self.after.extend(other.after.iter().cloned());
+        self.around.extend(other.around.iter().cloned());
+    }
+
+    /// Run the before-type middlewares.
+    ///
+    /// Returns `Flow::Stop` if one of them vetoed the update.
+    pub(crate) async fn run_before(
+        &self,
+        client: &mut Client,
+        update: &mut Update,
+        data: &mut Data,
+        context: &mut Context,
+    ) -> Flow {
+        run(&self.before, client, update, data, context).await
+    }
+
+    /// Run the after-type middlewares.
+    pub(crate) async fn run_after(
+        &self,
+        client: &mut Client,
+        update: &mut Update,
+        data: &mut Data,
+        context: &mut Context,
+    ) {
+        run(&self.after, client, update, data, context).await;
+    }
+}
+
+/// Run the `middlewares` in sequence, until one of them returns `Flow::Stop`.
+///
+/// The errors are logged and don't stop the sequence.
+async fn run(
+    middlewares: &[Arc<dyn MiddlewareImpl>],
+    client: &mut Client,
+    update: &mut Update,
+    data: &mut Data,
+    context: &mut Context,
+) -> Flow {
+    for mid in middlewares.iter() {
+        match mid.call(client, update, data, context).await {
+            Ok(Flow::Continue) => {}
+            Ok(Flow::Stop) => return Flow::Stop,
+            Err(e) => log::error!("Error while running middleware: {:?}", e),
+        }
+    }
+
+    Flow::Continue
+}
@@ -0,0 +1,95 @@
+// Copyright (C) 2024 AndrielFR
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::time::Duration;
+
+/// Retry Policy.
+///
+/// How the dispatcher retries when the update source fails,
+/// With an exponential backoff between the attempts.
+///
+/// By default it retries forever, starting at 1 second up to 1 minute.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_retries: Option<u32>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: u32,
+}
+
+impl RetryPolicy {
+    /// Construct a new retry policy, same as default.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Construct a retry policy which never retries.
+    ///
+    /// The first error stops the dispatcher.
+    pub fn never() -> Self {
+        Self::default().max_retries(0)
+    }
+
+    /// Set how many times it retries in a row before giving up.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = Some(max_retries);
+        self
+    }
+
+    /// Set the backoff before the first retry.
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Set the max backoff between two retries.
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Set how much the backoff is multiplied after each retry.
+    pub fn multiplier(mut self, multiplier: u32) -> Self {
+        self.multiplier = multiplier.max(1);
+        self
+    }
+
+    /// Get the backoff before the `attempt` retry, starting at `1`.
+    ///
+    /// `None` -> give up.
+    pub fn backoff(&self, attempt: u32) -> Option<Duration> {
+        if let Some(max_retries) = self.max_retries {
+            if attempt > max_retries {
+                return None;
+            }
+        }
+
+        let factor = self
+            .multiplier
+            .checked_pow(attempt.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+
+        Some(
+            self.initial_backoff
+                .checked_mul(factor)
+                .unwrap_or(self.max_backoff)
+                .min(self.max_backoff),
+        )
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: None,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            multiplier: 2,
+        }
+    }
+}
@@ -10,27 +10,67 @@ use std::sync::Arc;
 
 use async_recursion::async_recursion;
 use grammers_client::{Client, Update};
-use tokio::sync::Mutex;
 
-use crate::{traits::Module, Data, Handler, Middleware};
+use crate::{
+    middleware::{Endpoint, Middlewares},
+    traits::{ErrorHandlerCallback, FromData, Module},
+    Account, CommandInfo, Context, Data, Flow, Handler, Middleware, Next, Outcome, Propagation,
+    Result, State, Veto,
+};
 
 /// A Router, like a sub-disptacher.
 ///
 /// Receives `modules`, `handlers`, `middlewares` and `sub-routers`.
 #[derive(Clone, Default)]
 pub struct Router {
-    data: Data,
+    account: Option<String>,
+    data: Arc<Data>,
     handlers: Vec<Handler>,
-    middlewares: Vec<Arc<Mutex<Middleware>>>,
-    sub_routers: Vec<Box<Router>>,
+    middlewares: Middlewares,
+    outer_middlewares: Middlewares,
+    sub_routers: Vec<Router>,
+    error_handler: Option<Arc<dyn ErrorHandlerCallback>>,
+    propagation: Propagation,
+    veto: Option<Veto>,
 }
 
 impl Router {
+    /// Only handle the updates received by the account `name`.
+    ///
+    /// By default the updates of all accounts are handled.
+    pub fn account(mut self, name: impl Into<String>) -> Self {
+        self.account = Some(name.into());
+        self
+    }
+
+    /// Set what the router does after a handler matches.
+    ///
+    /// See [`Propagation`].
+    ///
+    /// [`Propagation`]: crate::Propagation
+    pub fn propagation(mut self, propagation: Propagation) -> Self {
+        self.propagation = propagation;
+        self
+    }
+
+    /// Set what the router does when a before-type middleware vetoes an update.
+    ///
+    /// By default the dispatcher's [`Veto`] is used, also by the sub-routers.
+    ///
+    /// [`Veto`]: crate::Veto
+    pub fn on_veto(mut self, veto: Veto) -> Self {
+        self.veto = Some(veto);
+        self
+    }
+
     /// Attach a new handler to the router.
     ///
     /// Which will be runned in sequence in which they were added.
     ///
-    /// Will stop running when a handler's filters match.
+    /// Will stop running when a handler's filters match, following the router's
+    /// [`Propagation`].
+    ///
+    /// [`Propagation`]: crate::Propagation
     pub fn add_handler(mut self, handler: Handler) -> Self {
         self.handlers.push(handler);
         self
@@ -40,15 +80,71 @@ impl Router {
     ///
     /// Which will be runned before or after each `handler`.
     pub fn add_middleware(mut self, middleware: Middleware) -> Self {
-        self.middlewares.push(Arc::new(Mutex::new(middleware)));
+        self.middlewares.push(middleware);
+        self
+    }
+
+    /// Attach a new outer middleware to the router.
+    ///
+    /// Which will be runned before, after or around the routing of each update reaching
+    /// The router, before any `handler` filter, unlike the ones of `add_middleware`.
+    ///
+    /// A before-type one may veto the update, see [`Veto`], an after-type one
+    /// Is runned even if no `handler` handled it.
+    ///
+    /// [`Veto`]: crate::Veto
+    pub fn add_outer_middleware(mut self, middleware: Middleware) -> Self {
+        self.outer_middlewares.push(middleware);
         self
     }
 
     /// Attach a new module to the router.
     ///
-    /// Which will be sent a mutable reference for each `middleware` and `handler`.
+    /// Which will be shared between each `middleware` and `handler` of the router and
+    /// Its sub-routers, overriding the inherited module of the same type.
+    ///
+    /// # Panics
+    ///
+    /// If a module of the same type was already added.
     pub fn add_module<M: Module>(mut self, module: M) -> Self {
-        self.data.push_module(Box::new(module));
+        Arc::make_mut(&mut self.data).push_module(module);
+        self
+    }
+
+    /// Attach a new module built at startup to the router.
+    ///
+    /// See [`FromData`].
+    ///
+    /// # Panics
+    ///
+    /// If a module of the same type was already added.
+    ///
+    /// [`FromData`]: crate::traits::FromData
+    pub fn provide<M: FromData>(mut self) -> Self {
+        Arc::make_mut(&mut self.data).push_provider::<M>();
+        self
+    }
+
+    /// Attach a new state to the router.
+    ///
+    /// Which can be read and changed by each `middleware` and `handler`
+    /// Through [`Data::with`] and [`Data::with_mut`].
+    ///
+    /// [`Data::with`]: crate::Data::with
+    /// [`Data::with_mut`]: crate::Data::with_mut
+    pub fn add_state<S: Send + Sync + 'static>(self, state: S) -> Self {
+        self.add_module(State::new(state))
+    }
+
+    /// Set the router's error handler.
+    ///
+    /// Receives `Fn(Error, &mut Client, &mut Update, &mut Data, &mut Context) -> Flow`,
+    /// Which will be runned when a `handler` fails, deciding if the update keeps
+    /// Being propagated.
+    ///
+    /// Also used by the sub-routers without their own error handler.
+    pub fn on_error<E: ErrorHandlerCallback>(mut self, error_handler: E) -> Self {
+        self.error_handler = Some(Arc::new(error_handler));
         self
     }
 
@@ -56,69 +152,197 @@ impl Router {
     ///
     /// Which will be runned if the current router don't handle the update.
     pub fn add_sub_router(mut self, sub_router: Router) -> Self {
-        self.sub_routers.push(Box::new(sub_router));
+        self.sub_routers.push(sub_router);
         self
     }
 
-    /// Attach a new boxed module to the router.
+    /// Get the commands of the router's handlers and its sub-routers.
+    pub fn commands(&self) -> Vec<CommandInfo> {
+        let mut commands = self
+            .handlers
+            .iter()
+            .flat_map(Handler::commands)
+            .collect::<Vec<_>>();
+
+        self.sub_routers.iter().for_each(|sub_router| {
+            commands.extend(sub_router.commands());
+        });
+
+        commands
+    }
+
+    /// Get the commands the account `name` handles, see [`Router::account`].
     ///
-    /// Which will be sent a mutable reference for each `middleware` and `handler`.
-    pub(crate) fn push_module(&mut self, module: Box<dyn Module>) {
-        self.data.push_module(module);
+    /// Same as [`Router::commands`], without the routers of other accounts.
+    pub fn commands_for(&self, name: &str) -> Vec<CommandInfo> {
+        if self.account.as_ref().is_some_and(|account| account != name) {
+            return Vec::new();
+        }
+
+        let mut commands = self
+            .handlers
+            .iter()
+            .flat_map(Handler::commands)
+            .collect::<Vec<_>>();
+
+        self.sub_routers.iter().for_each(|sub_router| {
+            commands.extend(sub_router.commands_for(name));
+        });
+
+        commands
+    }
+
+    /// Attach the `middlewares` to the router, after its own ones.
+    pub(crate) fn extend_middlewares(&mut self, middlewares: &Middlewares) {
+        self.middlewares.extend(middlewares);
+    }
+
+    /// Get the modules attached to the router and its sub-routers.
+    pub(crate) fn own_modules(&self) -> Vec<Arc<dyn Module>> {
+        let mut modules = self.data.own_modules();
+
+        self.sub_routers.iter().for_each(|sub_router| {
+            modules.extend(sub_router.own_modules());
+        });
+
+        modules
     }
 
-    /// Attach a new boxed middleware to the router.
+    /// Inherit, by reference, the modules of `parent`, then build the provided ones.
     ///
-    /// Which will be runned before or after each `handler`.
-    pub(crate) fn push_middleware(&mut self, middleware: Arc<Mutex<Middleware>>) {
-        self.middlewares.push(middleware);
+    /// Also shares the router's modules with its sub-routers.
+    ///
+    /// Fails if a provided module can't be built.
+    pub(crate) fn set_parent_data(&mut self, parent: &Arc<Data>) -> Result<()> {
+        let data = Arc::make_mut(&mut self.data);
+        data.set_parent(Arc::clone(parent));
+        data.resolve()?;
+
+        for sub_router in self.sub_routers.iter_mut() {
+            sub_router.set_parent_data(&self.data)?;
+        }
+
+        Ok(())
     }
 
-    /// Update sub-routers' data and middlewares.
+    /// Update sub-routers' middlewares.
     pub(crate) fn update_sub_routers(&mut self) {
         self.sub_routers.iter_mut().for_each(|sub_router| {
-            self.data.modules.iter().for_each(|module| {
-                sub_router.push_module(Box::clone(module));
-            });
-
-            self.middlewares.iter().for_each(|middleware| {
-                sub_router.push_middleware(Arc::clone(middleware));
-            });
+            sub_router.extend_middlewares(&self.middlewares);
         });
     }
 
     /// Handle the update sent by Telegram.
     ///
-    /// Starts by the before-type middlewares, handlers, after-types middlewares and if not handled
-    /// Send to the sub-routers.
+    /// Runs the outer middlewares around the routing: the handlers, each one with its
+    /// Before-type, after-types and around-type middlewares, and if not handled the
+    /// Sub-routers.
+    ///
+    /// Each update receives the router's data, sharing its modules, with the [`Account`] which
+    /// received it, and its own [`Context`], shared by the routers it passes through.
+    /// The [`Account`] is also left in the [`Context`], so the filters can see it.
+    ///
+    /// The handlers' errors are sent to the router's error handler or, if none, to the
+    /// inherited `error_handler`. Without any, they are logged and the router's
+    /// [`Propagation`] decides.
+    ///
+    /// [`Propagation`]: crate::Propagation
+    ///
+    /// [`Account`]: crate::Account
+    /// [`Context`]: crate::Context
     #[async_recursion]
-    pub(crate) async fn handle_update(&mut self, client: &mut Client, update: &mut Update) -> bool {
-        let mut update_handled = false;
-
-        moro::async_scope!(|scope| {
-            let data = &mut self.data;
-            let handlers = &mut self.handlers;
-            let middlewares = &mut self.middlewares;
-            let sub_routers = &mut self.sub_routers;
-
-            scope.spawn(async {
-                for handler in handlers.iter_mut() {
-                    if handler.handle(client, update, data, middlewares).await {
-                        update_handled = true;
-                        return;
+    pub(crate) async fn handle_update(
+        &self,
+        client: &mut Client,
+        update: &mut Update,
+        context: &mut Context,
+        account: &Account,
+        error_handler: Option<&'async_recursion Arc<dyn ErrorHandlerCallback>>,
+        veto: Veto,
+    ) -> bool {
+        let error_handler = self.error_handler.as_ref().or(error_handler);
+        let veto = self.veto.unwrap_or(veto);
+
+        if let Some(name) = self.account.as_ref() {
+            if name != account.name() {
+                return false;
+            }
+        }
+
+        let mut data = Data::scoped(Arc::clone(&self.data));
+        data.push_module(account.clone());
+        context.insert(account.clone());
+
+        let endpoint = Endpoint::Router {
+            router: self,
+            account,
+            error_handler,
+        };
+
+        match Next::new(&self.outer_middlewares, veto, endpoint)
+            .run(client, update, &mut data, context)
+            .await
+        {
+            Ok(outcome) => outcome != Outcome::Skipped,
+            Err(e) => {
+                log::error!("Error while running outer middleware: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Route the update through the handlers and, if not handled, the sub-routers.
+    ///
+    /// Returns if the update was handled.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn route(
+        &self,
+        client: &mut Client,
+        update: &mut Update,
+        data: &mut Data,
+        context: &mut Context,
+        account: &Account,
+        error_handler: Option<&Arc<dyn ErrorHandlerCallback>>,
+        veto: Veto,
+    ) -> bool {
+        for handler in self.handlers.iter() {
+            match handler
+                .handle(client, update, data, context, &self.middlewares, veto)
+                .await
+            {
+                Ok(Outcome::Skipped) => {}
+                Ok(Outcome::Handled(flow)) => {
+                    if flow.unwrap_or(self.propagation.on_success()) == Flow::Stop {
+                        return true;
                     }
                 }
+                Err(e) => {
+                    let flow = match error_handler {
+                        Some(error_handler) => {
+                            error_handler.call(e, client, update, data, context).await
+                        }
+                        None => {
+                            log::error!("Error while running handler: {}", e);
+                            self.propagation.on_error()
+                        }
+                    };
 
-                for sub_router in sub_routers.iter_mut() {
-                    if sub_router.handle_update(client, update).await {
-                        update_handled = true;
-                        return;
+                    if flow == Flow::Stop {
+                        return true;
                     }
                 }
-            });
-        })
-        .await;
+            }
+        }
+
+        for sub_router in self.sub_routers.iter() {
+            if sub_router
+                .handle_update(client, update, context, account, error_handler, veto)
+                .await
+            {
+                return true;
+            }
+        }
 
-        update_handled
+        false
     }
 }
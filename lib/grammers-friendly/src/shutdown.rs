@@ -0,0 +1,96 @@
+// Copyright (C) 2024 AndrielFR
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// A Shutdown Handle.
+///
+/// Stops the dispatcher programmatically, from any task.
+/// Its clones control the same dispatcher.
+///
+/// Get it through [`Dispatcher::shutdown_handle`].
+///
+/// [`Dispatcher::shutdown_handle`]: crate::Dispatcher::shutdown_handle
+#[derive(Clone, Debug)]
+pub struct ShutdownHandle {
+    sender: Arc<watch::Sender<Option<StopReason>>>,
+}
+
+impl ShutdownHandle {
+    pub(crate) fn new() -> Self {
+        Self {
+            sender: Arc::new(watch::Sender::new(None)),
+        }
+    }
+
+    /// Request the dispatcher to stop.
+    ///
+    /// It stops pulling new updates, drains the in-flight ones and runs the
+    /// shutdown hooks.
+    pub fn shutdown(&self) {
+        self.stop(StopReason::Shutdown);
+    }
+
+    /// Request the dispatcher to stop with `reason`.
+    ///
+    /// Only the first reason is kept.
+    pub(crate) fn stop(&self, reason: StopReason) {
+        self.sender.send_if_modified(|current| {
+            if current.is_none() {
+                *current = Some(reason);
+                return true;
+            }
+
+            false
+        });
+    }
+
+    /// Checks if the shutdown was requested.
+    pub fn is_shutdown(&self) -> bool {
+        self.sender.borrow().is_some()
+    }
+
+    /// Wait until the shutdown is requested.
+    ///
+    /// Returns the reason of the shutdown.
+    pub async fn wait(&self) -> StopReason {
+        let mut receiver = self.sender.subscribe();
+
+        let reason = match receiver.wait_for(Option::is_some).await {
+            Ok(reason) => *reason,
+            Err(_) => None,
+        };
+
+        reason.unwrap_or(StopReason::Shutdown)
+    }
+}
+
+impl Default for ShutdownHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Stop Reason.
+///
+/// Why the dispatcher stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// Ctrl-C was received.
+    CtrlC,
+
+    /// Requested through the [`ShutdownHandle`].
+    ///
+    /// [`ShutdownHandle`]: crate::ShutdownHandle
+    Shutdown,
+
+    /// The update source is exhausted.
+    SourceExhausted,
+}
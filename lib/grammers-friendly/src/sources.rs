@@ -0,0 +1,274 @@
+// Copyright (C) 2024 AndrielFR
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::{pin::pin, sync::Arc};
+
+use async_trait::async_trait;
+use futures_util::future::{select, Either};
+use grammers_client::{Client, Update};
+use tokio::sync::mpsc;
+
+use crate::{
+    traits::{SourceResult, UpdateSource},
+    Account, Error, RetryPolicy, DEFAULT_ACCOUNT,
+};
+
+/// The callback which observes the update source errors.
+pub(crate) type UpdateErrorCallback = dyn Fn(&Error, u32) + Send + Sync;
+
+/// Client update source.
+///
+/// Pulls the updates from [`Client::next_update`], the default source.
+///
+/// [`Client::next_update`]: grammers_client::Client::next_update
+#[derive(Clone)]
+pub struct ClientUpdateSource {
+    account: Account,
+}
+
+impl ClientUpdateSource {
+    /// Construct a new source for the `default` account.
+    pub fn new(client: Client) -> Self {
+        Self::named(DEFAULT_ACCOUNT, client)
+    }
+
+    /// Construct a new source with the account `name`.
+    pub fn named(name: impl Into<String>, client: Client) -> Self {
+        Self {
+            account: Account::new(name, client),
+        }
+    }
+}
+
+#[async_trait]
+impl UpdateSource for ClientUpdateSource {
+    fn accounts(&self) -> Vec<Account> {
+        vec![self.account.clone()]
+    }
+
+    async fn next_update(&mut self) -> SourceResult {
+        let update = self.account.client().next_update().await?;
+        Ok(Some((self.account.clone(), update)))
+    }
+}
+
+/// Channel update source.
+///
+/// Receives the updates from a [`mpsc::Receiver`], useful for tests.
+/// Exhausted when all its senders are dropped.
+///
+/// [`mpsc::Receiver`]: tokio::sync::mpsc::Receiver
+pub struct ChannelUpdateSource {
+    accounts: Vec<Account>,
+    receiver: mpsc::Receiver<(Account, Update)>,
+}
+
+impl ChannelUpdateSource {
+    /// Receives the `accounts` used by the lifecycle hooks, which can be empty,
+    /// And the `receiver`.
+    pub fn new(accounts: Vec<Account>, receiver: mpsc::Receiver<(Account, Update)>) -> Self {
+        Self { accounts, receiver }
+    }
+}
+
+#[async_trait]
+impl UpdateSource for ChannelUpdateSource {
+    fn accounts(&self) -> Vec<Account> {
+        self.accounts.clone()
+    }
+
+    async fn next_update(&mut self) -> SourceResult {
+        Ok(self.receiver.recv().await)
+    }
+}
+
+/// Fan-in update source.
+///
+/// Merges many sources into one, each of them pulled in its own task.
+/// Exhausted when all of them are exhausted.
+///
+/// Each source is retried on its own, following the [`RetryPolicy`], so its errors
+/// Are final, see [`UpdateSource::retries_errors`].
+///
+/// [`RetryPolicy`]: crate::RetryPolicy
+/// [`UpdateSource::retries_errors`]: crate::traits::UpdateSource::retries_errors
+pub struct FanInUpdateSource {
+    accounts: Vec<Account>,
+    sources: Vec<Box<dyn UpdateSource>>,
+    retry_policy: RetryPolicy,
+    on_error: Option<Arc<UpdateErrorCallback>>,
+    receiver: Option<mpsc::Receiver<SourceResult>>,
+    error: Option<Error>,
+}
+
+impl FanInUpdateSource {
+    pub fn new(sources: Vec<Box<dyn UpdateSource>>) -> Self {
+        let accounts = sources
+            .iter()
+            .flat_map(|source| source.accounts())
+            .collect();
+
+        Self {
+            accounts,
+            sources,
+            retry_policy: RetryPolicy::default(),
+            on_error: None,
+            receiver: None,
+            error: None,
+        }
+    }
+
+    /// Set how each source is retried when it fails.
+    ///
+    /// A source which gives up is stopped, the other sources keep running.
+    /// Once all of them are exhausted, the last error of the ones which gave up
+    /// Is returned instead of `Ok(None)`.
+    ///
+    /// See [`RetryPolicy`], by default it retries forever.
+    ///
+    /// [`RetryPolicy`]: crate::RetryPolicy
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Set a callback to observe the source errors.
+    ///
+    /// Receives the error and how many times in a row its source failed,
+    /// Runned before each retry and before giving up.
+    pub fn on_error<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&Error, u32) + Send + Sync + 'static,
+    {
+        self.on_error = Some(Arc::new(callback));
+        self
+    }
+
+    /// Spawn the task of each source.
+    fn spawn(&mut self) -> mpsc::Receiver<SourceResult> {
+        let (sender, receiver) = mpsc::channel(self.sources.len().max(1));
+
+        for mut source in self.sources.drain(..) {
+            let sender = sender.clone();
+            let policy = self.retry_policy.clone();
+            let on_error = self.on_error.clone();
+
+            tokio::spawn(async move {
+                let mut failures = 0;
+
+                loop {
+                    let closed = pin!(sender.closed());
+                    let update = pin!(source.next_update());
+
+                    let result = match select(closed, update).await {
+                        Either::Left(_) => break,
+                        Either::Right((result, _)) => result,
+                    };
+
+                    let update = match result {
+                        Ok(Some(update)) => {
+                            failures = 0;
+                            update
+                        }
+                        Ok(None) => break,
+                        Err(e) => {
+                            failures += 1;
+
+                            if let Some(callback) = on_error.as_ref() {
+                                callback(&e, failures);
+                            }
+
+                            let Some(backoff) = policy.backoff(failures) else {
+                                log::error!(
+                                    "Error while receiving update, giving up after {} failures: {}",
+                                    failures,
+                                    e
+                                );
+
+                                let _ = sender.send(Err(e)).await;
+                                break;
+                            };
+
+                            log::warn!(
+                                "Error while receiving update, retrying in {:?}: {}",
+                                backoff,
+                                e
+                            );
+
+                            let closed = pin!(sender.closed());
+                            let sleep = pin!(tokio::time::sleep(backoff));
+
+                            if let Either::Left(_) = select(closed, sleep).await {
+                                break;
+                            }
+
+                            continue;
+                        }
+                    };
+
+                    if sender.send(Ok(Some(update))).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        receiver
+    }
+}
+
+#[async_trait]
+impl UpdateSource for FanInUpdateSource {
+    fn accounts(&self) -> Vec<Account> {
+        self.accounts.clone()
+    }
+
+    async fn next_update(&mut self) -> SourceResult {
+        if self.receiver.is_none() {
+            self.receiver = Some(self.spawn());
+        }
+
+        let receiver = self.receiver.as_mut().expect("the receiver was just set");
+
+        loop {
+            match receiver.recv().await {
+                // Returned once the other sources are exhausted.
+                Some(Err(e)) => self.error = Some(e),
+                Some(result) => return result,
+                None => return self.error.take().map_or(Ok(None), Err),
+            }
+        }
+    }
+
+    fn retries_errors(&self) -> bool {
+        true
+    }
+}
+
+/// Merges many sources into one.
+///
+/// See [`FanInUpdateSource`].
+pub fn fan_in(sources: Vec<Box<dyn UpdateSource>>) -> FanInUpdateSource {
+    FanInUpdateSource::new(sources)
+}
+
+/// Merges many clients into one source, each of them tagged with its account `name`.
+///
+/// See [`FanInUpdateSource`].
+pub fn accounts<N: Into<String>>(
+    clients: impl IntoIterator<Item = (N, Client)>,
+) -> FanInUpdateSource {
+    FanInUpdateSource::new(
+        clients
+            .into_iter()
+            .map(|(name, client)| {
+                Box::new(ClientUpdateSource::named(name, client)) as Box<dyn UpdateSource>
+            })
+            .collect(),
+    )
+}
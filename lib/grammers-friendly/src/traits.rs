@@ -15,38 +15,390 @@ use grammers_client::{
 };
 
 use crate::{
-    filters::{AndFilter, NotFilter, OrFilter},
-    utils, Data,
+    filters::{AndFilter, NotFilter, OrFilter, QueryValue},
+    utils, Account, ArgError, CommandArgs, CommandInfo, Context, Data, Dependency, Error, Flow,
+    Next, Outcome, PendingArgs, Result, UsageError,
 };
 
 /// The async `func` from handlers
-pub trait AsyncFn<'a>: Send + Sync + 'static {
+///
+/// `M` tells the signatures apart: the raw one,
+/// `Fn(&mut Client, &mut Update, &mut Data, &mut Context)`, or
+/// Any combination of [`FromUpdate`] extractors.
+pub trait AsyncFn<'a, M>: Send + Sync + 'static {
     fn call(
         &'a self,
         client: &'a mut Client,
         update: &'a mut Update,
         data: &'a mut Data,
-    ) -> BoxFuture<'a, Result<(), Box<dyn std::error::Error>>>;
+        context: &'a mut Context,
+    ) -> BoxFuture<'a, Result<Outcome>>;
+
+    /// Checks if the extractors can be taken, before the middlewares run.
+    fn check(
+        &self,
+        client: &Client,
+        update: &Update,
+        data: &Data,
+        context: &Context,
+    ) -> Result<bool>;
+
+    /// The command arguments usage of the extractors, if any.
+    fn usage() -> Option<String>
+    where
+        Self: Sized,
+    {
+        None
+    }
 }
 
-impl<'a, T: ?Sized, F> AsyncFn<'a> for T
+/// Marker of the raw handler signature
+pub struct RawFn;
+
+impl<'a, T: ?Sized, F, R> AsyncFn<'a, RawFn> for T
 where
-    T: Fn(&'a mut Client, &'a mut Update, &'a mut Data) -> F + Send + Sync + 'static,
-    F: Future<Output = Result<(), Box<dyn std::error::Error>>> + Send + 'a,
+    T: Fn(&'a mut Client, &'a mut Update, &'a mut Data, &'a mut Context) -> F
+        + Send
+        + Sync
+        + 'static,
+    F: Future<Output = Result<R>> + Send + 'a,
+    R: IntoFlow,
 {
     fn call(
         &'a self,
         client: &'a mut Client,
         update: &'a mut Update,
         data: &'a mut Data,
-    ) -> BoxFuture<Result<(), Box<dyn std::error::Error>>> {
-        Box::pin(self(client, update, data))
+        context: &'a mut Context,
+    ) -> BoxFuture<'a, Result<Outcome>> {
+        let future = self(client, update, data, context);
+        Box::pin(async move { future.await.map(|r| Outcome::Handled(r.into_flow())) })
+    }
+
+    fn check(&self, _: &Client, _: &Update, _: &Data, _: &Context) -> Result<bool> {
+        Ok(true)
     }
 }
 
-pub trait AsyncFnCallback: for<'any> AsyncFn<'any> {}
+macro_rules! impl_async_fn {
+    ($($ty:ident),*) => {
+        #[allow(non_snake_case)]
+        impl<'a, T, F, R, $($ty,)*> AsyncFn<'a, ($($ty,)*)> for T
+        where
+            T: Fn($($ty),*) -> F + Send + Sync + 'static,
+            F: Future<Output = Result<R>> + Send + 'a,
+            R: IntoFlow,
+            $($ty: FromUpdate,)*
+        {
+            #[allow(unused_variables)]
+            fn call(
+                &'a self,
+                client: &'a mut Client,
+                update: &'a mut Update,
+                data: &'a mut Data,
+                context: &'a mut Context,
+            ) -> BoxFuture<'a, Result<Outcome>> {
+                $(
+                    let $ty = match $ty::from_update(client, update, data, context) {
+                        Ok(Some(value)) => value,
+                        Ok(None) => return Box::pin(async { Ok(Outcome::Skipped) }),
+                        Err(e) => return Box::pin(async { Err(e) }),
+                    };
+                )*
+
+                let future = self($($ty),*);
+                Box::pin(async move { future.await.map(|r| Outcome::Handled(r.into_flow())) })
+            }
+
+            #[allow(unused_variables)]
+            fn check(
+                &self,
+                client: &Client,
+                update: &Update,
+                data: &Data,
+                context: &Context,
+            ) -> Result<bool> {
+                $(
+                    if !$ty::check(client, update, data, context)? {
+                        return Ok(false);
+                    }
+                )*
+
+                Ok(true)
+            }
+
+            fn usage() -> Option<String> {
+                None$(.or_else($ty::usage))*
+            }
+        }
+    };
+}
+
+impl_async_fn!();
+impl_async_fn!(A1);
+impl_async_fn!(A1, A2);
+impl_async_fn!(A1, A2, A3);
+impl_async_fn!(A1, A2, A3, A4);
+impl_async_fn!(A1, A2, A3, A4, A5);
+impl_async_fn!(A1, A2, A3, A4, A5, A6);
+impl_async_fn!(A1, A2, A3, A4, A5, A6, A7);
+impl_async_fn!(A1, A2, A3, A4, A5, A6, A7, A8);
+
+pub trait AsyncFnCallback<M>: for<'any> AsyncFn<'any, M> {}
+
+impl<T, M> AsyncFnCallback<M> for T where for<'any> T: AsyncFn<'any, M> {}
+
+/// Extractor
+///
+/// Takes a piece of the update to a handler argument.
+///
+/// `None` -> the handler is skipped.
+/// `Err` -> the handler fails with it, like the arguments which couldn't be converted.
+pub trait FromUpdate: Sized + Send {
+    fn from_update(
+        client: &Client,
+        update: &Update,
+        data: &Data,
+        context: &Context,
+    ) -> Result<Option<Self>>;
+
+    /// Checks if it can be taken, before the middlewares run.
+    ///
+    /// By default takes it, see [`Handler::new`] for the order.
+    ///
+    /// [`Handler::new`]: crate::Handler::new
+    fn check(client: &Client, update: &Update, data: &Data, context: &Context) -> Result<bool> {
+        Self::from_update(client, update, data, context).map(|value| value.is_some())
+    }
+
+    /// The command arguments usage, shown in the help, see [`FromCommandArgs::usage`].
+    fn usage() -> Option<String> {
+        None
+    }
+}
+
+/// What handlers can return
+///
+/// `()` -> follow the router's [`Propagation`].
+/// [`Flow`] -> overrides it.
+///
+/// [`Propagation`]: crate::Propagation
+pub trait IntoFlow: Send {
+    fn into_flow(self) -> Option<Flow>;
+}
+
+impl IntoFlow for () {
+    fn into_flow(self) -> Option<Flow> {
+        None
+    }
+}
+
+impl IntoFlow for Flow {
+    fn into_flow(self) -> Option<Flow> {
+        Some(self)
+    }
+}
 
-impl<T> AsyncFnCallback for T where for<'any> T: AsyncFn<'any> {}
+/// What the [`QueryArgs`] values can be taken as
+///
+/// `None` -> the value is of another type.
+///
+/// [`QueryArgs`]: crate::filters::QueryArgs
+pub trait FromQueryValue: Sized {
+    fn from_query_value(value: &QueryValue) -> Option<Self>;
+}
+
+impl FromQueryValue for i64 {
+    fn from_query_value(value: &QueryValue) -> Option<Self> {
+        match value {
+            QueryValue::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl FromQueryValue for f64 {
+    fn from_query_value(value: &QueryValue) -> Option<Self> {
+        match value {
+            QueryValue::Float(value) => Some(*value),
+            QueryValue::Int(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+}
+
+impl FromQueryValue for bool {
+    fn from_query_value(value: &QueryValue) -> Option<Self> {
+        match value {
+            QueryValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl FromQueryValue for String {
+    fn from_query_value(value: &QueryValue) -> Option<Self> {
+        match value {
+            QueryValue::Str(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Command arguments parser
+///
+/// Usually derived with `#[derive(CommandArgs)]`, each field being a [`FromCommandArg`],
+/// Parsed in order from the [`CommandArgs`].
+///
+/// Taken by the handlers with the [`Command`] extractor.
+///
+/// [`CommandArgs`]: crate::CommandArgs
+/// [`Command`]: crate::extract::Command
+pub trait FromCommandArgs: Sized + Send + 'static {
+    /// The arguments usage, as `<user> [duration] <reason...>`.
+    fn usage() -> String;
+
+    fn from_command_args(args: &CommandArgs) -> std::result::Result<Self, UsageError>;
+}
+
+/// Command argument
+///
+/// Takes its value from the front of the arguments.
+///
+/// `Option` -> the argument isn't taken if invalid or missing.
+pub trait FromCommandArg: Sized {
+    fn from_command_arg(args: &mut PendingArgs) -> std::result::Result<Self, ArgError>;
+
+    /// The argument usage, from its `name`.
+    fn usage(name: &str) -> String {
+        format!("<{}>", name)
+    }
+}
+
+macro_rules! impl_from_command_arg {
+    ($($ty:ty),*) => {
+        $(
+            impl FromCommandArg for $ty {
+                fn from_command_arg(args: &mut PendingArgs) -> std::result::Result<Self, ArgError> {
+                    let arg = args.pop_front().ok_or(ArgError::Missing)?;
+                    arg.parse().map_err(|_| ArgError::Invalid(arg))
+                }
+            }
+        )*
+    };
+}
+
+impl_from_command_arg!(String, i32, i64, u32, u64, f64);
+
+impl FromCommandArg for bool {
+    fn from_command_arg(args: &mut PendingArgs) -> std::result::Result<Self, ArgError> {
+        let arg = args.pop_front().ok_or(ArgError::Missing)?;
+
+        match arg.to_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" => Ok(true),
+            "false" | "no" | "off" | "0" => Ok(false),
+            _ => Err(ArgError::Invalid(arg)),
+        }
+    }
+}
+
+impl<T: FromCommandArg> FromCommandArg for Option<T> {
+    fn from_command_arg(args: &mut PendingArgs) -> std::result::Result<Self, ArgError> {
+        let mut rest = *args;
+
+        match T::from_command_arg(&mut rest) {
+            Ok(value) => {
+                *args = rest;
+                Ok(Some(value))
+            }
+            Err(_) => Ok(None),
+        }
+    }
+
+    fn usage(name: &str) -> String {
+        format!("[{}]", T::usage(name).trim_matches(['<', '>']))
+    }
+}
+
+/// The async `func` from error handlers
+pub trait ErrorHandler<'a>: Send + Sync + 'static {
+    fn call(
+        &'a self,
+        error: Error,
+        client: &'a mut Client,
+        update: &'a mut Update,
+        data: &'a mut Data,
+        context: &'a mut Context,
+    ) -> BoxFuture<'a, Flow>;
+}
+
+impl<'a, T: ?Sized, F> ErrorHandler<'a> for T
+where
+    T: Fn(Error, &'a mut Client, &'a mut Update, &'a mut Data, &'a mut Context) -> F
+        + Send
+        + Sync
+        + 'static,
+    F: Future<Output = Flow> + Send + 'a,
+{
+    fn call(
+        &'a self,
+        error: Error,
+        client: &'a mut Client,
+        update: &'a mut Update,
+        data: &'a mut Data,
+        context: &'a mut Context,
+    ) -> BoxFuture<'a, Flow> {
+        Box::pin(self(error, client, update, data, context))
+    }
+}
+
+pub trait ErrorHandlerCallback: for<'any> ErrorHandler<'any> {}
+
+impl<T> ErrorHandlerCallback for T where for<'any> T: ErrorHandler<'any> {}
+
+/// The async `func` from shutdown hooks
+pub trait ShutdownHook: Send + Sync + 'static {
+    fn call(&self, client: Client) -> BoxFuture<'static, Result<()>>;
+}
+
+impl<T, F> ShutdownHook for T
+where
+    T: Fn(Client) -> F + Send + Sync + 'static,
+    F: Future<Output = Result<()>> + Send + 'static,
+{
+    fn call(&self, client: Client) -> BoxFuture<'static, Result<()>> {
+        Box::pin(self(client))
+    }
+}
+
+/// The result of [`UpdateSource::next_update`]
+pub type SourceResult = Result<Option<(Account, Update)>>;
+
+/// Update Source
+///
+/// Feeds the dispatcher with updates and the accounts which received them.
+#[async_trait]
+pub trait UpdateSource: Send + 'static {
+    /// The accounts behind the source.
+    ///
+    /// Used to run the lifecycle hooks, once per account.
+    fn accounts(&self) -> Vec<Account>;
+
+    /// Get the next update.
+    ///
+    /// `Ok(None)` -> the source is exhausted, which stops the dispatcher.
+    async fn next_update(&mut self) -> SourceResult;
+
+    /// Checks if the source retries its own errors.
+    ///
+    /// `true` -> its errors are final, stopping the dispatcher without retrying them.
+    /// `false` -> the dispatcher retries them following its [`RetryPolicy`] (default).
+    ///
+    /// [`RetryPolicy`]: crate::RetryPolicy
+    fn retries_errors(&self) -> bool {
+        false
+    }
+}
 
 /// Filter
 #[async_trait]
@@ -54,7 +406,15 @@ pub trait Filter: CloneFilter + Send + Sync + 'static {
     /// Needs to return bool
     /// `true` -> pass
     /// `false` -> not pass
-    async fn is_ok(&mut self, client: &Client, update: &Update) -> bool;
+    ///
+    /// May leave values in the `context` for the middlewares and handler,
+    /// Which are undone if the filter doesn't pass.
+    async fn is_ok(&self, client: &Client, update: &Update, context: &mut Context) -> bool;
+
+    /// The commands matched by the filter, used to generate the help
+    fn commands(&self) -> Vec<CommandInfo> {
+        Vec::new()
+    }
 
     /// Wrappes `self` and `second` into `AndFilter`
     fn and(self, second: impl Filter) -> AndFilter
@@ -101,57 +461,83 @@ impl Clone for Box<dyn Filter> {
 }
 
 /// Middleware
+///
+/// Shared between the updates, which may run it concurrently, so any state
+/// Changed by it must use interior mutability (`Mutex`, atomics, ...).
+///
+/// `Flow::Continue` -> run the next middlewares and the handler.
+/// `Flow::Stop` -> stop here, a before-type middleware vetoes the update,
+/// Which the router treats following its [`Veto`].
+///
+/// [`Veto`]: crate::Veto
 #[async_trait]
-pub trait MiddlewareImpl: CloneMiddlewareImpl + Send + Sync + 'static {
+pub trait MiddlewareImpl: Send + Sync + 'static {
     async fn call(
-        &mut self,
+        &self,
         client: &mut Client,
         update: &mut Update,
         data: &mut Data,
-    ) -> Result<(), Box<dyn std::error::Error>>;
+        context: &mut Context,
+    ) -> Result<Flow>;
 }
 
-pub trait CloneMiddlewareImpl {
-    fn clone_midddleware_impl(&self) -> Box<dyn MiddlewareImpl>;
+/// Around Middleware
+///
+/// Wraps the rest of the chain, which runs through `next`, so it can skip it,
+/// Time it, observe its result or retry it, with a copy of `next`.
+///
+/// Returning [`Outcome::Skipped`] without running `next` skips the handler.
+///
+/// [`Outcome::Skipped`]: crate::Outcome::Skipped
+#[async_trait]
+pub trait AroundMiddlewareImpl: Send + Sync + 'static {
+    async fn call(
+        &self,
+        client: &mut Client,
+        update: &mut Update,
+        data: &mut Data,
+        context: &mut Context,
+        next: Next<'_>,
+    ) -> Result<Outcome>;
 }
 
-impl<T> CloneMiddlewareImpl for T
-where
-    T: MiddlewareImpl + Clone,
-{
-    fn clone_midddleware_impl(&self) -> Box<dyn MiddlewareImpl> {
-        Box::new(self.clone())
+/// Module
+///
+/// Shared between the updates, so any state changed by it must use interior
+/// mutability (`Mutex`, atomics, ...) or be wrapped in a [`State`].
+///
+/// [`State`]: crate::State
+#[async_trait]
+pub trait Module: DowncastSync {
+    /// Runned when the dispatcher starts, before any update.
+    ///
+    /// Returning an error aborts the startup.
+    async fn on_startup(&self, _client: &Client) -> Result<()> {
+        Ok(())
     }
-}
 
-impl Clone for Box<dyn MiddlewareImpl> {
-    fn clone(&self) -> Self {
-        self.clone_midddleware_impl()
+    /// Runned when the dispatcher stops, after the in-flight updates.
+    async fn on_shutdown(&self, _client: &Client) -> Result<()> {
+        Ok(())
     }
 }
 
-/// Module
-pub trait Module: DowncastSync + CloneModule {}
-
 impl_downcast!(sync Module);
 
-pub trait CloneModule {
-    fn clone_module(&self) -> Box<dyn Module>;
-}
-
-impl<T> CloneModule for T
-where
-    T: Module + Clone,
-{
-    fn clone_module(&self) -> Box<dyn Module> {
-        Box::new(self.clone())
+/// Module built from the other modules
+///
+/// Added through `provide`, built when the dispatcher starts, after the
+/// Modules it depends on.
+pub trait FromData: Module + Sized {
+    /// The modules needed by `from_data`.
+    ///
+    /// The missing ones abort the startup.
+    fn dependencies() -> Vec<Dependency> {
+        Vec::new()
     }
-}
 
-impl Clone for Box<dyn Module> {
-    fn clone(&self) -> Self {
-        self.clone_module()
-    }
+    /// Build the module.
+    fn from_data(data: &Data) -> Result<Self>;
 }
 
 pub trait GetChat {
@@ -10,10 +10,16 @@ use std::cmp::Ordering;
 
 use grammers_client::{
     button::{self, Inline},
+    grammers_tl_types::{
+        self as tl,
+        functions::bots::{ResetBotCommands, SetBotCommands},
+    },
     types::{CallbackQuery, Chat, Message},
     Update,
 };
 
+use crate::{CommandInfo, CommandScope};
+
 /// Get the chat from [Update]
 pub fn get_chat(update: &Update) -> Option<Chat> {
     let mut chat = None;
@@ -166,3 +172,323 @@ pub fn gen_page_buttons(
 
     buttons
 }
+
+/// Max of commands per bot command menu.
+const MAX_BOT_COMMANDS: usize = 100;
+
+/// The scopes of the bot command menu, from the widest.
+const SCOPES: [CommandScope; 4] = [
+    CommandScope::Default,
+    CommandScope::PrivateChats,
+    CommandScope::GroupChats,
+    CommandScope::GroupAdmins,
+];
+
+/// Get the bot command menu of the `commands`, one request per scope and language.
+///
+/// Only the commands with the `/` prefix, valid names and not hidden are listed,
+/// Described by their name if they have no description.
+///
+/// Telegram shows only the narrowest scope, so each one also lists the commands of the
+/// Wider ones, see [`CommandScope::includes`]. The same for the languages, which list
+/// All the commands of the scope.
+///
+/// Each menu is cut to its first 100 commands, the max Telegram allows.
+///
+/// [`CommandScope::includes`]: crate::CommandScope::includes
+pub fn bot_commands(commands: &[CommandInfo]) -> Vec<SetBotCommands> {
+    let commands = menu_commands(commands);
+    let mut requests = Vec::new();
+
+    for scope in SCOPES {
+        let mut commands = scope_commands(&commands, scope);
+
+        if commands.len() > MAX_BOT_COMMANDS {
+            log::warn!(
+                "The bot command menu of {:?} has {} commands, only the first {} are listed",
+                scope,
+                commands.len(),
+                MAX_BOT_COMMANDS
+            );
+
+            commands.truncate(MAX_BOT_COMMANDS);
+        }
+
+        for language in scope_languages(&commands, scope) {
+            requests.push(SetBotCommands {
+                scope: scope.into(),
+                lang_code: language.to_string(),
+                commands: commands
+                    .iter()
+                    .map(|command| {
+                        let description = command
+                            .description_in(language)
+                            .unwrap_or(command.name())
+                            .chars()
+                            .take(256)
+                            .collect();
+
+                        tl::types::BotCommand {
+                            command: command.name().to_string(),
+                            description,
+                        }
+                        .into()
+                    })
+                    .collect(),
+            });
+        }
+    }
+
+    requests
+}
+
+/// Get the requests which clear the bot command menus not set by [`bot_commands`].
+///
+/// Covers every scope with the languages of the `commands`, so a menu of a language
+/// No longer used by any of them isn't cleared.
+pub fn reset_bot_commands(commands: &[CommandInfo]) -> Vec<ResetBotCommands> {
+    let commands = menu_commands(commands);
+    let languages = languages(&commands);
+
+    let mut requests = Vec::new();
+
+    for scope in SCOPES {
+        let set = scope_languages(&scope_commands(&commands, scope), scope);
+
+        languages
+            .iter()
+            .filter(|language| !set.contains(language))
+            .for_each(|language| {
+                requests.push(ResetBotCommands {
+                    scope: scope.into(),
+                    lang_code: language.to_string(),
+                })
+            });
+    }
+
+    requests
+}
+
+/// Get the commands which can be in the bot command menu.
+fn menu_commands(commands: &[CommandInfo]) -> Vec<&CommandInfo> {
+    commands
+        .iter()
+        .filter(|command| !command.is_hidden() && command.prefixes().contains(&'/'))
+        .filter(|command| {
+            let name = command.name();
+            let valid = !name.is_empty()
+                && name.len() <= 32
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+            if !valid {
+                log::warn!("The command `{}` can't be in the bot command menu", name);
+            }
+
+            valid
+        })
+        .collect()
+}
+
+/// Get the `commands` listed in the `scope`, empty if none of them is of it.
+fn scope_commands<'a>(commands: &[&'a CommandInfo], scope: CommandScope) -> Vec<&'a CommandInfo> {
+    let scopes = |command: &CommandInfo| match command.scopes() {
+        [] => vec![CommandScope::Default],
+        scopes => scopes.to_vec(),
+    };
+
+    if !commands
+        .iter()
+        .any(|command| scopes(command).contains(&scope))
+    {
+        return Vec::new();
+    }
+
+    commands
+        .iter()
+        .filter(|command| scopes(command).into_iter().any(|s| scope.includes(s)))
+        .copied()
+        .collect()
+}
+
+/// Get the languages of the `scope` menus, none if it has no `commands`.
+fn scope_languages<'a>(commands: &[&'a CommandInfo], scope: CommandScope) -> Vec<&'a str> {
+    match commands.is_empty() && scope != CommandScope::Default {
+        true => Vec::new(),
+        false => languages(commands),
+    }
+}
+
+/// Get the languages of the `commands`, starting by the default one, `""`.
+fn languages<'a>(commands: &[&'a CommandInfo]) -> Vec<&'a str> {
+    let mut languages = vec![""];
+
+    commands
+        .iter()
+        .flat_map(|command| command.languages())
+        .for_each(|language| {
+            if !languages.contains(&language) {
+                languages.push(language);
+            }
+        });
+
+    languages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{filters::CommandFilter, traits::Filter};
+
+    fn info(filter: CommandFilter) -> CommandInfo {
+        filter.commands().remove(0)
+    }
+
+    /// Get the `(name, description)` of the menu of `scope` in `language`.
+    fn menu(
+        requests: &[SetBotCommands],
+        scope: CommandScope,
+        language: &str,
+    ) -> Option<Vec<(String, String)>> {
+        let scope = tl::enums::BotCommandScope::from(scope);
+        let request = requests
+            .iter()
+            .find(|request| request.scope == scope && request.lang_code == language)?;
+
+        Some(
+            request
+                .commands
+                .iter()
+                .map(|command| {
+                    let tl::enums::BotCommand::Command(command) = command;
+                    (command.command.clone(), command.description.clone())
+                })
+                .collect(),
+        )
+    }
+
+    fn names(menu: Option<Vec<(String, String)>>) -> Vec<String> {
+        menu.unwrap_or_default()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    #[test]
+    fn bot_commands_skip_unlisted() {
+        let commands = [
+            info(CommandFilter::new("/", "start")),
+            info(CommandFilter::new("/", "secret").hidden(true)),
+            info(CommandFilter::new("!", "bang")),
+            info(CommandFilter::new("/!", "both")),
+            info(CommandFilter::new("/", "Upper")),
+            info(CommandFilter::new("/", "with-dash")),
+            info(CommandFilter::new("/", "a".repeat(33))),
+        ];
+
+        let requests = bot_commands(&commands);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            names(menu(&requests, CommandScope::Default, "")),
+            vec!["start", "both"]
+        );
+    }
+
+    #[test]
+    fn bot_commands_merge_scopes() {
+        let commands = [
+            info(CommandFilter::new("/", "start")),
+            info(CommandFilter::new("/", "settings").scope(CommandScope::PrivateChats)),
+            info(CommandFilter::new("/", "rules").scope(CommandScope::GroupChats)),
+            info(CommandFilter::new("/", "ban").scope(CommandScope::GroupAdmins)),
+            info(
+                CommandFilter::new("/", "about")
+                    .scope(CommandScope::Default)
+                    .scope(CommandScope::PrivateChats),
+            ),
+        ];
+
+        let requests = bot_commands(&commands);
+        assert_eq!(requests.len(), 4);
+        assert_eq!(
+            names(menu(&requests, CommandScope::Default, "")),
+            vec!["start", "about"]
+        );
+        assert_eq!(
+            names(menu(&requests, CommandScope::PrivateChats, "")),
+            vec!["start", "settings", "about"]
+        );
+        assert_eq!(
+            names(menu(&requests, CommandScope::GroupChats, "")),
+            vec!["start", "rules", "about"]
+        );
+        assert_eq!(
+            names(menu(&requests, CommandScope::GroupAdmins, "")),
+            vec!["start", "rules", "ban", "about"]
+        );
+    }
+
+    #[test]
+    fn bot_commands_skip_unused_scopes() {
+        let commands = [
+            info(CommandFilter::new("/", "start")),
+            info(CommandFilter::new("/", "ban").scope(CommandScope::GroupAdmins)),
+        ];
+
+        let requests = bot_commands(&commands);
+        assert_eq!(requests.len(), 2);
+        assert!(menu(&requests, CommandScope::PrivateChats, "").is_none());
+        assert!(menu(&requests, CommandScope::GroupChats, "").is_none());
+
+        let resets = reset_bot_commands(&commands);
+        assert_eq!(resets.len(), 2);
+        assert!(resets.iter().all(|reset| reset.lang_code.is_empty()));
+        assert!(resets
+            .iter()
+            .any(|reset| reset.scope == CommandScope::PrivateChats.into()));
+        assert!(resets
+            .iter()
+            .any(|reset| reset.scope == CommandScope::GroupChats.into()));
+    }
+
+    #[test]
+    fn bot_commands_cut_long_menus() {
+        let commands = (0..150)
+            .map(|n| info(CommandFilter::new("/", format!("cmd{}", n))))
+            .collect::<Vec<_>>();
+
+        let requests = bot_commands(&commands);
+        assert_eq!(requests.len(), 1);
+
+        let names = names(menu(&requests, CommandScope::Default, ""));
+        assert_eq!(names.len(), MAX_BOT_COMMANDS);
+        assert_eq!(names.last().map(String::as_str), Some("cmd99"));
+    }
+
+    #[test]
+    fn bot_commands_fall_back_languages() {
+        let commands = [
+            info(
+                CommandFilter::new("/", "start")
+                    .description("Start the bot")
+                    .description_in("pt", "Inicia o bot"),
+            ),
+            info(CommandFilter::new("/", "help").description("Show the commands")),
+            info(CommandFilter::new("/", "ping")),
+            info(CommandFilter::new("/", "long").description("a".repeat(300))),
+        ];
+
+        let requests = bot_commands(&commands);
+        assert_eq!(requests.len(), 2);
+
+        let default = menu(&requests, CommandScope::Default, "").unwrap();
+        assert_eq!(default[0], ("start".into(), "Start the bot".into()));
+        assert_eq!(default[2], ("ping".into(), "ping".into()));
+        assert_eq!(default[3].1.len(), 256);
+
+        let pt = menu(&requests, CommandScope::Default, "pt").unwrap();
+        assert_eq!(pt[0], ("start".into(), "Inicia o bot".into()));
+        assert_eq!(pt[1], ("help".into(), "Show the commands".into()));
+        assert_eq!(pt[2], ("ping".into(), "ping".into()));
+    }
+}
@@ -0,0 +1,31 @@
+// Copyright (C) 2024 AndrielFR
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{parse::Parser, punctuated::Punctuated, Expr, Token};
+
+/// Expand `command!(command)` or `command!(prefixes, command)`.
+pub(crate) fn expand(input: TokenStream) -> syn::Result<TokenStream> {
+    let args = Punctuated::<Expr, Token![,]>::parse_terminated.parse2(input.clone())?;
+
+    match args.len() {
+        1 => {
+            let command = &args[0];
+            Ok(quote! { ::grammers_friendly::filters::command("/!", #command) })
+        }
+        2 => {
+            let (prefixes, command) = (&args[0], &args[1]);
+            Ok(quote! { ::grammers_friendly::filters::command(#prefixes, #command) })
+        }
+        _ => Err(syn::Error::new_spanned(
+            input,
+            "expected `command!(command)` or `command!(prefixes, command)`",
+        )),
+    }
+}
@@ -0,0 +1,113 @@
+// Copyright (C) 2024 AndrielFR
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Fields, Type};
+
+/// Expand `#[derive(CommandArgs)]`.
+pub(crate) fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields.named.iter().collect(),
+            Fields::Unit => Vec::new(),
+            Fields::Unnamed(fields) => {
+                return Err(syn::Error::new_spanned(
+                    fields,
+                    "`CommandArgs` needs named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                ident,
+                "`CommandArgs` can only be derived for structs",
+            ))
+        }
+    };
+
+    // `Rest` takes all the remaining arguments.
+    if let Some(field) = fields.iter().rev().skip(1).find(|field| is_rest(&field.ty)) {
+        return Err(syn::Error::new_spanned(
+            &field.ty,
+            "`Rest` must be the last field",
+        ));
+    }
+
+    let idents = fields.iter().map(|field| &field.ident).collect::<Vec<_>>();
+    let types = fields.iter().map(|field| &field.ty).collect::<Vec<_>>();
+    let names = fields
+        .iter()
+        .filter_map(|field| field.ident.as_ref())
+        .map(|ident| ident.to_string().trim_start_matches("r#").to_string())
+        .collect::<Vec<_>>();
+
+    Ok(quote! {
+        impl #impl_generics ::grammers_friendly::traits::FromCommandArgs for #ident #ty_generics
+            #where_clause
+        {
+            fn usage() -> ::std::string::String {
+                let usage: ::std::vec::Vec<::std::string::String> = ::std::vec![
+                    #(<#types as ::grammers_friendly::traits::FromCommandArg>::usage(#names)),*
+                ];
+
+                usage.join(" ")
+            }
+
+            fn from_command_args(
+                args: &::grammers_friendly::CommandArgs,
+            ) -> ::std::result::Result<Self, ::grammers_friendly::UsageError> {
+                let mut rest = ::grammers_friendly::PendingArgs::new(args);
+
+                let value = Self {
+                    #(
+                        #idents: <#types as ::grammers_friendly::traits::FromCommandArg>::from_command_arg(
+                            &mut rest,
+                        )
+                        .map_err(|e| ::grammers_friendly::UsageError::new::<Self>(args, Some(#names), e))?,
+                    )*
+                };
+
+                if let Some(arg) = rest.pop_front() {
+                    return Err(::grammers_friendly::UsageError::new::<Self>(
+                        args,
+                        None,
+                        ::grammers_friendly::ArgError::Unexpected(arg),
+                    ));
+                }
+
+                Ok(value)
+            }
+        }
+    })
+}
+
+/// Checks if `ty` is `Rest`, or `Option<Rest>`.
+fn is_rest(ty: &Type) -> bool {
+    let Type::Path(path) = ty else {
+        return false;
+    };
+    let Some(segment) = path.path.segments.last() else {
+        return false;
+    };
+
+    match segment.ident.to_string().as_str() {
+        "Rest" => true,
+        "Option" => match &segment.arguments {
+            syn::PathArguments::AngleBracketed(args) => args
+                .args
+                .iter()
+                .any(|arg| matches!(arg, syn::GenericArgument::Type(ty) if is_rest(ty))),
+            _ => false,
+        },
+        _ => false,
+    }
+}
@@ -6,27 +6,63 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+mod command;
+mod command_args;
+
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput};
+
 /// Command filter macro.
 ///
 /// Used with pré-setted prefixes `/` and `!`.
 ///
 /// # Example
 ///
-/// ```
+/// ```ignore
 /// macros::command!("start")
 /// ```
 ///
 /// Which is equivalent to
 ///
-/// ```
+/// ```ignore
 /// filters::command("/!", "start")
 /// ```
-#[macro_export]
-macro_rules! command {
-    ($command:expr) => {
-        ::grammers_friendly::filters::command("/!", $command)
-    };
-    ($prefixes:expr, $command:expr) => {
-        ::grammers_friendly::filters::command($prefixes, $command)
-    };
+#[proc_macro]
+pub fn command(input: TokenStream) -> TokenStream {
+    command::expand(input.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Command arguments derive.
+///
+/// Implements `FromCommandArgs`, parsing each field, in order, from the command arguments.
+///
+/// The fields implement `FromCommandArg`, `Option` ones may be omitted and
+/// `Rest` takes the remaining ones.
+///
+/// # Example
+///
+/// ```ignore
+/// #[derive(CommandArgs)]
+/// struct Ban {
+///     user: UserRef,
+///     duration: Option<Duration>,
+///     reason: Rest,
+/// }
+///
+/// async fn ban(Command(ban): Command<Ban>, message: Message) -> Result<()> {
+///     ...
+/// }
+/// ```
+///
+/// Which parses `/ban @foo 1h spam`, replying `/ban <user> [duration] <reason...>`
+/// If it fails.
+#[proc_macro_derive(CommandArgs)]
+pub fn derive_command_args(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    command_args::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
 }